@@ -0,0 +1,76 @@
+/// The time animations follow, counted in game ticks instead of the wall clock.
+///
+/// It only moves forward while the game is being played or an animated screen is shown,
+/// so pausing freezes every animation.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct AnimationClock {
+    pub ticks: u64,
+}
+
+impl AnimationClock {
+    /// The game runs at 60 frames per second and the clock ticks once every frame.
+    pub const TICKS_PER_SECOND: u64 = 60;
+
+    /// Advances the clock by one frame.
+    pub fn tick(&mut self) {
+        self.ticks += 1;
+    }
+
+    /// The whole seconds of game time since the clock started.
+    pub fn seconds(&self) -> u64 {
+        self.ticks / AnimationClock::TICKS_PER_SECOND
+    }
+}
+
+/// The state of one animated thing, so not everything moves in sync.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Animation {
+    /// How many ticks every frame is shown.
+    pub ticks_per_frame: u64,
+    /// How many ticks this animation is ahead of the clock.
+    pub offset: u64,
+}
+
+impl Animation {
+    pub fn new(ticks_per_frame: u64, offset: u64) -> Self {
+        Animation {
+            ticks_per_frame: ticks_per_frame.max(1),
+            offset,
+        }
+    }
+
+    /// The frame that should be shown at the time of the clock.
+    pub fn frame(&self, clock: &AnimationClock, frame_count: usize) -> usize {
+        let ticks = clock.ticks + self.offset;
+        (ticks / self.ticks_per_frame) as usize % frame_count.max(1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frames_advance_with_the_clock() {
+        let animation = Animation::new(3, 0);
+        let mut clock = AnimationClock::default();
+
+        let frames: Vec<_> = (0..12)
+            .map(|_| {
+                let frame = animation.frame(&clock, 2);
+                clock.tick();
+                frame
+            })
+            .collect();
+
+        assert_eq!(frames, vec![0, 0, 0, 1, 1, 1, 0, 0, 0, 1, 1, 1]);
+    }
+
+    #[test]
+    fn test_offset_animations_are_out_of_sync() {
+        let clock = AnimationClock { ticks: 10 };
+
+        assert_eq!(Animation::new(5, 0).frame(&clock, 4), 2);
+        assert_eq!(Animation::new(5, 5).frame(&clock, 4), 3);
+    }
+}
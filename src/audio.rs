@@ -3,7 +3,8 @@ use std::{fs::File, io::BufReader};
 use rodio::{Decoder, OutputStream, OutputStreamHandle, Sink};
 
 pub struct AudioPlayer {
-    _device_stream: (OutputStream, OutputStreamHandle),
+    /// `None` when the player is muted.
+    _device_stream: Option<(OutputStream, OutputStreamHandle)>,
     pub background: Track,
     pub loose_song: Track,
     pub win_song: Track,
@@ -12,6 +13,8 @@ pub struct AudioPlayer {
 pub struct Track {
     path: String,
     pub sink: Sink,
+    /// Muted tracks don't even load their file.
+    muted: bool,
 }
 
 impl Track {
@@ -20,10 +23,24 @@ impl Track {
         Track {
             path: track_path,
             sink,
+            muted: false,
+        }
+    }
+
+    /// Creates a track that isn't connected to any device, so it never makes a sound.
+    pub fn muted(track_path: String) -> Self {
+        let (sink, _) = Sink::new_idle();
+        Track {
+            path: track_path,
+            sink,
+            muted: true,
         }
     }
 
     pub fn play(&self) {
+        if self.muted {
+            return;
+        }
         let path = BufReader::new(File::open(&self.path).unwrap());
         let source = Decoder::new(path).unwrap();
         self.sink.append(source)
@@ -43,7 +60,7 @@ impl AudioPlayer {
         let file_path = format!("{}sounds/{}", asset_dir, "loose.mp3");
         let loose_song = Track::new(file_path, &stream_handle);
 
-        let _device_stream = (stream, stream_handle);
+        let _device_stream = Some((stream, stream_handle));
         AudioPlayer {
             _device_stream,
             background,
@@ -51,4 +68,16 @@ impl AudioPlayer {
             loose_song,
         }
     }
+
+    /// Creates a player that doesn't need an audio device, for when there's no one to listen.
+    pub fn muted(asset_dir: &str) -> Self {
+        let track = |name: &str| Track::muted(format!("{}sounds/{}", asset_dir, name));
+
+        AudioPlayer {
+            _device_stream: None,
+            background: track("background.mp3"),
+            win_song: track("win.mp3"),
+            loose_song: track("loose.mp3"),
+        }
+    }
 }
@@ -1,14 +1,63 @@
 use std::{
-    fs::File,
-    io::{BufWriter, Write},
+    fs::{self, File},
+    io::{self, BufWriter, Write},
 };
 
 use crate::color::Color;
 
+const FILE_HEADER_SIZE: usize = 14;
 const DIB_HEADER_SIZE: usize = 40;
 const BMP_HEADER_SIZE: usize = 54;
-const BMP_PIXEL_OFFSET: usize = 54;
-const BMP_BITS_PER_PIXEL: usize = 24;
+const BMP_V4_HEADER_SIZE: usize = 108;
+const BMP_V5_HEADER_SIZE: usize = 124;
+/// No compression.
+const BI_RGB: u32 = 0;
+/// No compression, with masks that say where every channel is.
+const BI_BITFIELDS: u32 = 3;
+/// The colors are in the sRGB color space, written backwards as the spec asks.
+const LCS_SRGB: &[u8; 4] = b"BGRs";
+/// The rendering intent that keeps the colors as close as possible.
+const LCS_GM_IMAGES: u32 = 4;
+
+/// How the pixels of a BMP file are stored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BmpFormat {
+    /// 24 bits per pixel with a `BITMAPINFOHEADER`.
+    #[default]
+    Rgb24,
+    /// 32 bits per pixel with an alpha channel and a `BITMAPV5HEADER`.
+    Rgba32,
+}
+
+/// The order the rows of a BMP file are stored in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RowOrder {
+    #[default]
+    BottomUp,
+    TopDown,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct BmpOptions {
+    pub format: BmpFormat,
+    pub row_order: RowOrder,
+}
+
+impl BmpFormat {
+    fn bytes_per_pixel(&self) -> usize {
+        match self {
+            BmpFormat::Rgb24 => 3,
+            BmpFormat::Rgba32 => 4,
+        }
+    }
+
+    fn header_size(&self) -> usize {
+        match self {
+            BmpFormat::Rgb24 => BMP_HEADER_SIZE,
+            BmpFormat::Rgba32 => FILE_HEADER_SIZE + BMP_V5_HEADER_SIZE,
+        }
+    }
+}
 
 /// Writes a BMP file using the buffer data and the given width and height of the image.
 ///
@@ -21,46 +70,89 @@ pub fn write_bmp_file(
     buffer: &[u32],
     width: usize,
     height: usize,
+) -> std::io::Result<()> {
+    write_bmp_file_with_options(
+        file_path,
+        buffer,
+        None,
+        width,
+        height,
+        BmpOptions::default(),
+    )
+}
+
+/// Writes a BMP file like `write_bmp_file`, choosing how the pixels are stored.
+///
+/// * `alphas`: The alpha of every pixel, only used by `BmpFormat::Rgba32`.
+///   Every pixel is opaque when it's `None`.
+pub fn write_bmp_file_with_options(
+    file_path: &str,
+    buffer: &[u32],
+    alphas: Option<&[u8]>,
+    width: usize,
+    height: usize,
+    options: BmpOptions,
 ) -> std::io::Result<()> {
     let writer = File::create(file_path)?;
     let mut writer = BufWriter::new(writer);
-    let padded_buffer = pad_buffer(buffer, width);
-    let header = generate_header(width, height, padded_buffer.len());
-
-    // println!("Padded buffer: {:?}", padded_buffer);
 
-    writer.write_all(&header)?;
-    writer.write_all(&padded_buffer)?;
+    writer.write_all(&encode_bmp_with_options(
+        buffer, alphas, width, height, options,
+    ))?;
     writer.flush()
 }
 
-/// Writes a .bmp header into the given `writer`.
-fn generate_header(width: usize, height: usize, data_byte_length: usize) -> Vec<u8> {
-    let byte_file_size = BMP_HEADER_SIZE as u32 + data_byte_length as u32;
+/// Encodes the buffer data as the bytes of a 24 bits BMP file.
+pub fn encode_bmp(buffer: &[u32], width: usize, height: usize) -> Vec<u8> {
+    encode_bmp_with_options(buffer, None, width, height, BmpOptions::default())
+}
 
-    // println!(
-    //     "File size: {}={} + {}",
-    //     byte_file_size, BMP_HEADER_SIZE, data_byte_length
-    // );
+/// Encodes the buffer data as the bytes of a BMP file, see `write_bmp_file_with_options`.
+pub fn encode_bmp_with_options(
+    buffer: &[u32],
+    alphas: Option<&[u8]>,
+    width: usize,
+    height: usize,
+    options: BmpOptions,
+) -> Vec<u8> {
+    let padded_buffer = pad_buffer(buffer, alphas, width, options);
+    let header = generate_header(width, height, padded_buffer.len(), options);
 
-    // println!(
-    //     "The data length (including padding) is: {:?}\n{:?}",
-    //     data_byte_length,
-    //     data_byte_length.to_le_bytes()
-    // );
+    header.into_iter().chain(padded_buffer).collect()
+}
+
+/// Generates the file header and the DIB header of a .bmp file.
+fn generate_header(
+    width: usize,
+    height: usize,
+    data_byte_length: usize,
+    options: BmpOptions,
+) -> Vec<u8> {
+    let BmpOptions { format, row_order } = options;
+    let header_size = format.header_size();
+    let byte_file_size = header_size as u32 + data_byte_length as u32;
+    // Top-down images are marked with a negative height.
+    let height = match row_order {
+        RowOrder::BottomUp => height as i32,
+        RowOrder::TopDown => -(height as i32),
+    };
+    let (dib_header_size, bits_per_pixel, compression) = match format {
+        BmpFormat::Rgb24 => (DIB_HEADER_SIZE, 24u16, BI_RGB),
+        BmpFormat::Rgba32 => (BMP_V5_HEADER_SIZE, 32u16, BI_BITFIELDS),
+    };
 
-    [&b'B', &b'M']
+    let mut header: Vec<u8> = [&b'B', &b'M']
         .into_iter()
-        .chain(&byte_file_size.to_le_bytes()[0..4])
+        .chain(&byte_file_size.to_le_bytes())
         .chain(&[0, 0, 0, 0]) // Reserved, must be 0.
-        .chain(&BMP_PIXEL_OFFSET.to_le_bytes()[0..4])
-        .chain(&DIB_HEADER_SIZE.to_le_bytes()[0..4])
-        .chain(&width.to_le_bytes()[0..4])
-        .chain(&height.to_le_bytes()[0..4])
+        .chain(&(header_size as u32).to_le_bytes()) // Where the pixels start.
+        .chain(&(dib_header_size as u32).to_le_bytes())
+        .chain(&(width as i32).to_le_bytes())
+        .chain(&height.to_le_bytes())
         .chain(&[1, 0]) // This must always be 1 and use two bytes.
-        .chain(&BMP_BITS_PER_PIXEL.to_le_bytes()[0..4]) // 24 bits per pixel.
-        .chain(&[0, 0]) // No compression is being used.
-        .chain(&(data_byte_length as u32).to_le_bytes()[0..4]) // Image data size.
+        .chain(&bits_per_pixel.to_le_bytes())
+        .chain(&compression.to_le_bytes())
+        .chain(&(data_byte_length as u32).to_le_bytes()) // Image data size.
         .chain(&[0, 0, 0, 0]) // horizontal resolution (0 by default)
         .chain(&[0, 0, 0, 0]) // vertical resolution (0 by default)
         .chain(&[0, 0, 0, 0]) // the number of colors in the pallete, 0 means 2^n colors.
@@ -68,46 +160,313 @@ fn generate_header(width: usize, height: usize, data_byte_length: usize) -> Vec<
         // 0 means all colors are important.
         .chain(&[0, 0, 0, 0])
         .copied()
-        .collect()
+        .collect();
+
+    if format == BmpFormat::Rgba32 {
+        header.extend(0x00ff_0000u32.to_le_bytes()); // Red mask.
+        header.extend(0x0000_ff00u32.to_le_bytes()); // Green mask.
+        header.extend(0x0000_00ffu32.to_le_bytes()); // Blue mask.
+        header.extend(0xff00_0000u32.to_le_bytes()); // Alpha mask.
+        header.extend(LCS_SRGB);
+        header.extend([0; 36]); // Endpoints, unused with sRGB.
+        header.extend([0; 12]); // Gamma, unused with sRGB.
+        header.extend(LCS_GM_IMAGES.to_le_bytes());
+        header.extend([0; 12]); // No color profile and a reserved field.
+    }
+
+    header
 }
 
-/// Formats the given buffer data to have the padding necessary according to width
-fn pad_buffer(buffer: &[u32], width: usize) -> Vec<u8> {
-    let padding_bytes_count = compute_padding_bytes_per_row(width);
-    let padding_per_row: Vec<u8> = (0..padding_bytes_count).map(|_| 0).collect();
+/// Formats the given buffer data to have the padding necessary according to width,
+/// with the rows in the order given by the options.
+fn pad_buffer(buffer: &[u32], alphas: Option<&[u8]>, width: usize, options: BmpOptions) -> Vec<u8> {
+    if width == 0 {
+        return vec![];
+    }
+
+    let padding_bytes_count = compute_padding_bytes_per_row(width, options.format);
+    let rows = buffer.chunks(width).enumerate().map(|(y, row)| {
+        let pixels = row.iter().enumerate().flat_map(|(x, c)| {
+            let Color { r, g, b } = c.into();
+            let a = alphas.map_or(u8::MAX, |alphas| alphas[y * width + x]);
 
-    let buffer: Vec<u8> = buffer
-        .iter()
-        .map(|c| c.into())
-        .enumerate()
-        .flat_map(|(i, Color { r, g, b })| {
             // The order is not a typo
             // Microsoft do be smoking...
-            let vec = vec![b, g, r];
-
-            if (i + 1) % width == 0 && i != 0 {
-                vec.into_iter().chain(padding_per_row.clone()).collect()
-            } else {
-                vec
+            match options.format {
+                BmpFormat::Rgb24 => vec![b, g, r],
+                BmpFormat::Rgba32 => vec![b, g, r, a],
             }
-        })
-        .collect();
+        });
 
-    // The BMP format doesn't start at the top left corner
+        pixels
+            .chain(std::iter::repeat_n(0, padding_bytes_count))
+            .collect::<Vec<u8>>()
+    });
+
+    // The BMP format usually doesn't start at the top left corner
     // instead it starts at the bottom left corner, so we need to reverse
-    // the buffer by chunks to have it in the order the BMP format expects.
-    buffer
-        .as_slice()
-        .chunks(width * 3 + padding_bytes_count)
-        .rev()
-        .flatten()
-        .copied()
-        .collect()
+    // the buffer by rows to have it in the order the BMP format expects.
+    match options.row_order {
+        RowOrder::BottomUp => rows.rev().flatten().collect(),
+        RowOrder::TopDown => rows.flatten().collect(),
+    }
 }
 
-fn compute_padding_bytes_per_row(width: usize) -> usize {
-    let color_bytes_per_row = width * 3;
+fn compute_padding_bytes_per_row(width: usize, format: BmpFormat) -> usize {
+    let color_bytes_per_row = width * format.bytes_per_pixel();
+
+    color_bytes_per_row.div_ceil(4) * 4 - color_bytes_per_row
+}
+
+/// An image decoded from a BMP file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BmpImage {
+    pub width: usize,
+    pub height: usize,
+    /// The color of every pixel, row by row starting from the top left corner.
+    /// It uses the same layout as `Framebuffer::buffer`.
+    pub buffer: Vec<u32>,
+    /// The alpha of every pixel, images without an alpha channel are completely opaque.
+    pub alphas: Vec<u8>,
+}
+
+/// Reads a BMP file, see `decode_bmp`.
+pub fn read_bmp_file(file_path: &str) -> io::Result<BmpImage> {
+    decode_bmp(&fs::read(file_path)?)
+}
+
+/// Decodes the bytes of an uncompressed 24 or 32 bits per pixel BMP file,
+/// stored either bottom-up or top-down.
+///
+/// 32 bits BMPs may use bit fields to say where every channel is,
+/// only masks of whole bytes are supported.
+pub fn decode_bmp(bytes: &[u8]) -> io::Result<BmpImage> {
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+    let u16_at = |offset: usize| {
+        bytes
+            .get(offset..offset + 2)
+            .map(|b| u16::from_le_bytes([b[0], b[1]]))
+            .ok_or_else(|| invalid("The BMP header is truncated"))
+    };
+    let u32_at = |offset: usize| {
+        bytes
+            .get(offset..offset + 4)
+            .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .ok_or_else(|| invalid("The BMP header is truncated"))
+    };
+
+    if !bytes.starts_with(b"BM") {
+        return Err(invalid("Not a BMP file"));
+    }
+
+    let pixel_offset = u32_at(10)? as usize;
+    let dib_header_size = u32_at(14)? as usize;
+    let width = u32_at(18)? as i32;
+    let height = u32_at(22)? as i32;
+    let bits_per_pixel = u16_at(28)?;
+    let compression = u32_at(30)?;
+
+    if width <= 0 || height == 0 {
+        return Err(invalid("The BMP has no pixels"));
+    }
+    // A negative height means the rows are stored from the top to the bottom.
+    let top_down = height < 0;
+    let (width, height) = (width as usize, height.unsigned_abs() as usize);
+
+    // The byte of every pixel where each channel is, as (red, green, blue, alpha).
+    let channels = match (bits_per_pixel, compression) {
+        (24 | 32, BI_RGB) => (2, 1, 0, None),
+        (32, BI_BITFIELDS) => {
+            // The masks come right after the header,
+            // the alpha mask is only part of the V4 and V5 headers.
+            let masks_offset = FILE_HEADER_SIZE + DIB_HEADER_SIZE;
+            let byte_of = |mask: u32| match mask {
+                0x0000_00ff => Ok(0),
+                0x0000_ff00 => Ok(1),
+                0x00ff_0000 => Ok(2),
+                0xff00_0000 => Ok(3),
+                _ => Err(invalid("Unsupported BMP bit field")),
+            };
+            let alpha_mask = if dib_header_size >= BMP_V4_HEADER_SIZE {
+                u32_at(masks_offset + 12)?
+            } else {
+                0
+            };
+
+            (
+                byte_of(u32_at(masks_offset)?)?,
+                byte_of(u32_at(masks_offset + 4)?)?,
+                byte_of(u32_at(masks_offset + 8)?)?,
+                match alpha_mask {
+                    0 => None,
+                    mask => Some(byte_of(mask)?),
+                },
+            )
+        }
+        _ => {
+            return Err(invalid(
+                "Only uncompressed 24 and 32 bits BMPs are supported",
+            ))
+        }
+    };
+
+    let bytes_per_pixel = bits_per_pixel as usize / 8;
+    // Rows are padded to a multiple of 4 bytes.
+    let row_size = (width * bytes_per_pixel).div_ceil(4) * 4;
+    let pixels = bytes
+        .get(pixel_offset..)
+        .filter(|pixels| pixels.len() >= row_size * height)
+        .ok_or_else(|| invalid("The BMP pixel data is truncated"))?;
+
+    let mut buffer = Vec::with_capacity(width * height);
+    let mut alphas = Vec::with_capacity(width * height);
+    for y in 0..height {
+        let row = if top_down { y } else { height - 1 - y };
+        let row = &pixels[row * row_size..][..width * bytes_per_pixel];
+
+        for pixel in row.chunks(bytes_per_pixel) {
+            let (r, g, b, a) = channels;
+            let color = Color::new(pixel[r], pixel[g], pixel[b]);
+            buffer.push(color.into());
+            alphas.push(a.map_or(255, |a| pixel[a]));
+        }
+    }
+
+    Ok(BmpImage {
+        width,
+        height,
+        buffer,
+        alphas,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gradient(width: usize, height: usize) -> Vec<u32> {
+        (0..width * height)
+            .map(|i| {
+                ((i * 37) as u32 & 0xff) << 16 | ((i * 11) as u32 & 0xff) << 8 | i as u32 & 0xff
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_round_trip_every_row_padding() {
+        for width in 1..=5 {
+            let buffer = gradient(width, 3);
+
+            let image = decode_bmp(&encode_bmp(&buffer, width, 3)).unwrap();
+
+            assert_eq!((image.width, image.height), (width, 3));
+            assert_eq!(image.buffer, buffer);
+            assert!(image.alphas.iter().all(|&a| a == 255));
+        }
+    }
+
+    #[test]
+    fn test_round_trip_through_a_file() {
+        let buffer = gradient(7, 5);
+        let file_path = std::env::temp_dir().join("hornystein_round_trip.bmp");
+        let file_path = file_path.to_str().unwrap();
+
+        write_bmp_file(file_path, &buffer, 7, 5).unwrap();
+        let image = read_bmp_file(file_path).unwrap();
+
+        assert_eq!(image.buffer, buffer);
+    }
+
+    #[test]
+    fn test_decodes_top_down_32_bits() {
+        // A 2x2 image, every pixel stored as blue, green, red and an unused byte.
+        let mut bytes = encode_bmp(&[0; 4], 2, 2)[..BMP_HEADER_SIZE].to_vec();
+        bytes[22..26].copy_from_slice(&(-2i32).to_le_bytes());
+        bytes[28..30].copy_from_slice(&32u16.to_le_bytes());
+        bytes.extend([0x03, 0x02, 0x01, 0x00, 0x06, 0x05, 0x04, 0x00]);
+        bytes.extend([0x09, 0x08, 0x07, 0x00, 0x0c, 0x0b, 0x0a, 0x00]);
+
+        let image = decode_bmp(&bytes).unwrap();
+
+        assert_eq!(image.buffer, vec![0x010203, 0x040506, 0x070809, 0x0a0b0c]);
+    }
+
+    #[test]
+    fn test_rejects_other_files() {
+        assert!(decode_bmp(b"GIF89a").is_err());
+        assert!(decode_bmp(&encode_bmp(&[0; 4], 2, 2)[..60]).is_err());
+    }
+
+    #[test]
+    fn test_default_header_bytes() {
+        let header = generate_header(3, 2, 24, BmpOptions::default());
+
+        assert_eq!(header.len(), 54);
+        assert_eq!(&header[0..2], b"BM");
+        assert_eq!(&header[2..6], &78u32.to_le_bytes()); // File size.
+        assert_eq!(&header[10..14], &54u32.to_le_bytes()); // Pixel offset.
+        assert_eq!(&header[14..18], &40u32.to_le_bytes()); // DIB header size.
+        assert_eq!(&header[18..22], &3i32.to_le_bytes());
+        assert_eq!(&header[22..26], &2i32.to_le_bytes());
+        assert_eq!(&header[26..28], &[1, 0]);
+        assert_eq!(&header[28..30], &24u16.to_le_bytes());
+        assert_eq!(&header[30..34], &BI_RGB.to_le_bytes());
+        assert_eq!(&header[34..38], &24u32.to_le_bytes());
+    }
+
+    #[test]
+    fn test_v5_header_bytes() {
+        let options = BmpOptions {
+            format: BmpFormat::Rgba32,
+            row_order: RowOrder::TopDown,
+        };
+
+        let header = generate_header(3, 2, 24, options);
+
+        assert_eq!(header.len(), 138);
+        assert_eq!(&header[2..6], &162u32.to_le_bytes()); // File size.
+        assert_eq!(&header[10..14], &138u32.to_le_bytes()); // Pixel offset.
+        assert_eq!(&header[14..18], &124u32.to_le_bytes()); // DIB header size.
+        assert_eq!(&header[22..26], &(-2i32).to_le_bytes()); // Top-down.
+        assert_eq!(&header[28..30], &32u16.to_le_bytes());
+        assert_eq!(&header[30..34], &BI_BITFIELDS.to_le_bytes());
+        assert_eq!(&header[54..58], &0x00ff_0000u32.to_le_bytes());
+        assert_eq!(&header[58..62], &0x0000_ff00u32.to_le_bytes());
+        assert_eq!(&header[62..66], &0x0000_00ffu32.to_le_bytes());
+        assert_eq!(&header[66..70], &0xff00_0000u32.to_le_bytes());
+        assert_eq!(&header[70..74], b"BGRs");
+        assert_eq!(&header[122..126], &LCS_GM_IMAGES.to_le_bytes());
+    }
+
+    #[test]
+    fn test_round_trip_with_alpha_in_both_orders() {
+        let buffer = gradient(3, 2);
+        let alphas = [0, 50, 100, 150, 200, 255];
+
+        for row_order in [RowOrder::BottomUp, RowOrder::TopDown] {
+            let options = BmpOptions {
+                format: BmpFormat::Rgba32,
+                row_order,
+            };
+            let bytes = encode_bmp_with_options(&buffer, Some(&alphas), 3, 2, options);
+
+            let image = decode_bmp(&bytes).unwrap();
+
+            assert_eq!(image.buffer, buffer);
+            assert_eq!(image.alphas, alphas);
+        }
+    }
+
+    #[test]
+    fn test_top_down_rows_are_not_reversed() {
+        let options = BmpOptions {
+            row_order: RowOrder::TopDown,
+            ..BmpOptions::default()
+        };
+
+        let bytes = encode_bmp_with_options(&[0x010203, 0x040506], None, 1, 2, options);
 
-    ((4.0 * (color_bytes_per_row as f32 / 4.0).ceil()) - color_bytes_per_row as f32).floor()
-        as usize
+        assert_eq!(&bytes[54..], &[3, 2, 1, 0, 6, 5, 4, 0]);
+        assert_eq!(decode_bmp(&bytes).unwrap().buffer, vec![0x010203, 0x040506]);
+    }
 }
@@ -0,0 +1,176 @@
+//! The levels of a campaign, played one after the other.
+//!
+//! A campaign manifest has a `[level]` section for every level, in the order they're played,
+//! with one `key = value` setting per line like the `[options]` of a maze:
+//!
+//! ```text
+//! [level]
+//! maze = maze
+//! assets = night_assets/
+//! bunnies = 10
+//! moon_speed = 0.00025
+//! ```
+//!
+//! Only `maze` is required, the rest fall back to the defaults.
+
+use std::fs;
+
+/// How many lolibunnies hide in a level when the manifest doesn't say.
+pub const DEFAULT_LOLIBUNNIES: usize = 10;
+/// How much the moon moves every frame when the manifest doesn't say,
+/// it sets after `1.0 / moon_speed` frames.
+pub const DEFAULT_MOON_SPEED: f32 = 2.5e-4;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Level {
+    pub maze_file: String,
+    pub assets_dir: String,
+    /// How many lolibunnies are spawned in the maze.
+    pub lolibunnies: usize,
+    pub moon_speed: f32,
+}
+
+impl Level {
+    /// A level with the default settings.
+    pub fn new(maze_file: &str, assets_dir: &str) -> Self {
+        Level {
+            maze_file: maze_file.to_string(),
+            assets_dir: assets_dir.to_string(),
+            lolibunnies: DEFAULT_LOLIBUNNIES,
+            moon_speed: DEFAULT_MOON_SPEED,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Campaign {
+    pub levels: Vec<Level>,
+}
+
+impl Campaign {
+    /// A campaign with a single level, for playing a maze on its own.
+    pub fn single(level: Level) -> Self {
+        Campaign {
+            levels: vec![level],
+        }
+    }
+
+    /// Reads the manifest in `file_name`, levels without `assets` use `default_assets_dir`.
+    pub fn load(file_name: &str, default_assets_dir: &str) -> Self {
+        let manifest = fs::read_to_string(file_name).expect("Couldn't open campaign file!");
+        Campaign::parse(&manifest, default_assets_dir)
+    }
+
+    /// Parses a manifest, empty lines and `#` comments are skipped.
+    pub fn parse(manifest: &str, default_assets_dir: &str) -> Self {
+        let mut sections: Vec<Vec<(&str, &str)>> = vec![];
+        for line in manifest.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if line == "[level]" {
+                sections.push(vec![]);
+                continue;
+            }
+
+            let (key, value) = line.split_once('=').unwrap_or_else(|| {
+                panic!("Campaign settings should look like `key = value`, found `{line}`!")
+            });
+            sections
+                .last_mut()
+                .expect("Campaign settings should be inside a `[level]` section!")
+                .push((key.trim(), value.trim()));
+        }
+
+        let levels: Vec<Level> = sections
+            .into_iter()
+            .map(|settings| {
+                let maze_file = settings
+                    .iter()
+                    .find(|(key, _)| *key == "maze")
+                    .map(|(_, maze)| *maze)
+                    .expect("Every level of a campaign needs a `maze`!");
+
+                let mut level = Level::new(maze_file, default_assets_dir);
+                for (key, value) in settings {
+                    match key {
+                        "maze" => {}
+                        "assets" => level.assets_dir = value.to_string(),
+                        "bunnies" => {
+                            level.lolibunnies =
+                                value.parse().expect("`bunnies` must be a whole number!");
+                            assert!(
+                                level.lolibunnies > 0,
+                                "The level in {maze_file} needs at least one lolibunny!"
+                            );
+                        }
+                        "moon_speed" => {
+                            level.moon_speed =
+                                value.parse().expect("`moon_speed` must be a number!")
+                        }
+                        key => panic!("Unknown campaign setting `{key}`!"),
+                    }
+                }
+
+                level
+            })
+            .collect();
+        assert!(!levels.is_empty(), "A campaign needs at least one level!");
+
+        Campaign { levels }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_levels_keep_their_order_and_defaults() {
+        let manifest = "
+            # The first night
+            [level]
+            maze = maze
+            bunnies = 3
+
+            [level]
+            maze = crypt
+            assets = crypt_assets/
+            moon_speed = 0.0005
+        ";
+
+        let campaign = Campaign::parse(manifest, "night_assets/");
+
+        assert_eq!(
+            campaign.levels,
+            vec![
+                Level {
+                    lolibunnies: 3,
+                    ..Level::new("maze", "night_assets/")
+                },
+                Level {
+                    moon_speed: 0.0005,
+                    ..Level::new("crypt", "crypt_assets/")
+                },
+            ]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "at least one lolibunny")]
+    fn test_levels_need_bunnies() {
+        Campaign::parse("[level]\nmaze = maze\nbunnies = 0", "night_assets/");
+    }
+
+    #[test]
+    fn test_the_shipped_campaign_loads() {
+        let campaign = Campaign::load("night.campaign", "night_assets/");
+
+        assert!(campaign.levels.len() > 1);
+        for level in campaign.levels {
+            assert!(fs::metadata(&level.maze_file).is_ok());
+            assert!(fs::metadata(&level.assets_dir).is_ok());
+        }
+    }
+}
@@ -0,0 +1,248 @@
+use std::collections::VecDeque;
+
+use glm::Vec2;
+
+use super::{Entity, Surroundings};
+use crate::{
+    navigation::{cell_center, cell_of, Cell, Doors, Navigator},
+    BoardCell, Message,
+};
+
+/// How fast hunters walk when the moon has just risen, in cells per frame.
+const MIN_SPEED: f32 = 0.03;
+/// How fast hunters walk when the moon is about to set, in cells per frame.
+const MAX_SPEED: f32 = 0.07;
+/// How close a hunter has to get to the player to catch them, in cells.
+const REACH: f32 = 0.4;
+
+/// Chases the player around the maze, the player loses if it catches them.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Hunter {
+    pub position: Vec2,
+    /// The angle the hunter is looking at.
+    pub orientation: f32,
+    /// The cells the hunter is going to walk through, the next one first.
+    pub path: VecDeque<Cell>,
+    /// The cell of the player when the path was found,
+    /// `None` if there was no way to get to them.
+    target: Option<Cell>,
+}
+
+impl Hunter {
+    pub fn new(position: Vec2) -> Self {
+        Hunter {
+            position,
+            orientation: 0.0,
+            path: VecDeque::new(),
+            target: None,
+        }
+    }
+
+    /// How far the hunter walks every frame, in cells.
+    /// Hunters get faster as the night goes on.
+    pub fn speed(moon_phase: f32) -> f32 {
+        MIN_SPEED + (MAX_SPEED - MIN_SPEED) * moon_phase.clamp(0.0, 1.0)
+    }
+}
+
+impl Entity<Hunter> for Hunter {
+    fn tick(data: Self, surroundings: &Surroundings) -> (Self, Option<Message>) {
+        let Surroundings {
+            board,
+            player,
+            moon_phase,
+        } = surroundings;
+        let Hunter {
+            mut position,
+            mut orientation,
+            mut path,
+            mut target,
+        } = data;
+
+        let (block_width, _) = board.cell_dimensions;
+        if nalgebra_glm::distance(&position, &player.position) <= REACH * block_width {
+            return (
+                Hunter {
+                    position,
+                    orientation,
+                    path,
+                    target,
+                },
+                Some(Message::YouLost),
+            );
+        }
+
+        // Hunters can open doors.
+        let navigator = Navigator::new(board, Doors::CanOpen);
+        let cell = cell_of(board, &position);
+        let player_cell = cell_of(board, &player.position);
+        if target != Some(player_cell) {
+            match navigator.a_star(cell, player_cell) {
+                Some(found) => {
+                    path = found.into();
+                    target = Some(player_cell);
+                }
+                None => {
+                    // The player is out of reach, look again next frame.
+                    path.clear();
+                    target = None;
+                }
+            }
+        }
+
+        let speed = Hunter::speed(*moon_phase) * block_width;
+        let mut message = None;
+        let goal = match path.front() {
+            Some(&next) => {
+                let (i, j) = next;
+                match &board.cells[j][i] {
+                    BoardCell::Door(door) if !door.is_passable() => {
+                        // Waits for the door to open.
+                        message = Some(Message::OpenDoor(next));
+                        None
+                    }
+                    _ if !navigator.is_walkable(next) => {
+                        // Something got in the way, like a push-wall, look for another way.
+                        path.clear();
+                        target = None;
+                        None
+                    }
+                    _ => Some(cell_center(board, next)),
+                }
+            }
+            None if cell == player_cell => Some(player.position),
+            // There's no way to the player, so it waits.
+            None => None,
+        };
+
+        if let Some(goal) = goal {
+            let to_goal = goal - position;
+            let distance = glm::length(&to_goal);
+            if distance <= speed {
+                position = goal;
+                path.pop_front();
+            } else {
+                position += to_goal / distance * speed;
+            }
+            if distance > 0.0 {
+                orientation = to_goal.y.atan2(to_goal.x);
+            }
+        }
+
+        let hunter = Hunter {
+            position,
+            orientation,
+            path,
+            target,
+        };
+        (hunter, message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{board, player_at};
+
+    #[test]
+    fn test_hunters_catch_the_player_around_corners() {
+        let board = board(&["+++++", "+   +", "+++ +", "+   +", "+++++"]);
+        let player = player_at(Vec2::new(15.0, 35.0));
+        let surroundings = Surroundings {
+            board: &board,
+            player: &player,
+            moon_phase: 0.5,
+        };
+        let mut hunter = Hunter::new(Vec2::new(15.0, 15.0));
+
+        let mut message = None;
+        for _ in 0..200 {
+            let (next, emitted) = Hunter::tick(hunter, &surroundings);
+            hunter = next;
+            if emitted.is_some() {
+                message = emitted;
+                break;
+            }
+        }
+
+        assert!(matches!(message, Some(Message::YouLost)));
+    }
+
+    #[test]
+    fn test_hunters_open_closed_doors() {
+        let board = board(&["+++++", "+ d +", "+++++"]);
+        let player = player_at(Vec2::new(35.0, 15.0));
+        let surroundings = Surroundings {
+            board: &board,
+            player: &player,
+            moon_phase: 0.0,
+        };
+
+        let (hunter, message) = Hunter::tick(Hunter::new(Vec2::new(15.0, 15.0)), &surroundings);
+
+        assert!(matches!(message, Some(Message::OpenDoor((2, 1)))));
+        assert_eq!(hunter.position, Vec2::new(15.0, 15.0));
+    }
+
+    #[test]
+    fn test_hunters_dont_walk_through_walls() {
+        let board = board(&["+++++", "+ + +", "+++++"]);
+        let player = player_at(Vec2::new(35.0, 15.0));
+        let surroundings = Surroundings {
+            board: &board,
+            player: &player,
+            moon_phase: 1.0,
+        };
+        let start = Vec2::new(15.0, 15.0);
+        let mut hunter = Hunter::new(start);
+
+        for _ in 0..200 {
+            let (next, message) = Hunter::tick(hunter, &surroundings);
+            assert!(message.is_none());
+            hunter = next;
+        }
+
+        assert_eq!(hunter.position, start);
+    }
+
+    #[test]
+    fn test_hunters_go_around_push_walls_that_cut_their_way() {
+        let mut board = board(&["+++++++", "+     +", "+ +s+ +", "+     +", "+++++++"]);
+        let player = player_at(Vec2::new(55.0, 15.0));
+        let mut hunter = Hunter::new(Vec2::new(15.0, 15.0));
+
+        let mut message = None;
+        for tick in 0..500 {
+            if tick == 1 {
+                // Slides into the corridor the hunter is about to walk through.
+                let occupied = [cell_of(&board, &hunter.position), (5, 1)];
+                board.push_wall((3, 2), (0, -1), &occupied);
+                assert!(board.is_reserved((3, 1)));
+            }
+            let surroundings = Surroundings {
+                board: &board,
+                player: &player,
+                moon_phase: 1.0,
+            };
+            let (next, emitted) = Hunter::tick(hunter, &surroundings);
+            hunter = next;
+
+            let cell = cell_of(&board, &hunter.position);
+            assert!(Navigator::new(&board, Doors::CanOpen).is_walkable(cell));
+            if emitted.is_some() {
+                message = emitted;
+                break;
+            }
+            board.tick(&[cell, (5, 1)]);
+        }
+
+        assert!(matches!(board.cells[1][3], BoardCell::PushWall(_)));
+        assert!(matches!(message, Some(Message::YouLost)));
+    }
+
+    #[test]
+    fn test_hunters_get_faster_as_the_moon_sets() {
+        assert!(Hunter::speed(0.0) < Hunter::speed(0.5));
+        assert!(Hunter::speed(0.5) < Hunter::speed(1.0));
+    }
+}
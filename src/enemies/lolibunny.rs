@@ -1,14 +1,254 @@
+use std::collections::VecDeque;
+
 use glm::Vec2;
+use rand::seq::SliceRandom;
+
+use super::{Entity, Surroundings};
+use crate::{
+    animation::Animation,
+    navigation::{cell_center, cell_of, line_of_sight, Cell, Doors, Navigator},
+};
 
-use super::Entity;
+/// How fast bunnies hop around while nothing is going on, in cells per frame.
+const WANDER_SPEED: f32 = 0.02;
+/// How fast bunnies run away from the player, in cells per frame.
+const FLEE_SPEED: f32 = 0.06;
+/// How far bunnies can see the player from, in cells.
+const SIGHT_DISTANCE: f32 = 6.0;
+/// How far away bunnies go when wandering, in steps.
+const WANDER_RADIUS: usize = 5;
+/// How far away bunnies look for a place to hide, in steps.
+const FLEE_RADIUS: usize = 12;
+/// How many frames bunnies stay hidden before going out again.
+const HIDE_TICKS: u32 = 360;
+
+/// What a bunny is doing.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum BunnyMood {
+    /// Hops around randomly.
+    Wandering,
+    /// Runs away after seeing the player.
+    Fleeing,
+    /// Waits where it ran to, for the given number of frames.
+    Hiding(u32),
+}
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct LoliBunny {
     pub position: Vec2,
+    /// The angle the bunny is looking at.
+    pub orientation: f32,
+    pub animation: Animation,
+    pub mood: BunnyMood,
+    /// The cells the bunny is going to hop through, the next one first.
+    pub path: VecDeque<Cell>,
+}
+
+impl LoliBunny {
+    pub fn new(position: Vec2, orientation: f32, animation: Animation) -> Self {
+        LoliBunny {
+            position,
+            orientation,
+            animation,
+            mood: BunnyMood::Wandering,
+            path: VecDeque::new(),
+        }
+    }
 }
 
 impl Entity<LoliBunny> for LoliBunny {
-    fn tick(data: Self) -> (Self, Option<crate::Message>) {
-        (data, None)
+    fn tick(data: Self, surroundings: &Surroundings) -> (Self, Option<crate::Message>) {
+        let Surroundings { board, player, .. } = surroundings;
+        let LoliBunny { mood, mut path, .. } = data;
+        let cell = cell_of(board, &data.position);
+        // Bunnies can't open doors.
+        let navigator = Navigator::new(board, Doors::OnlyOpen);
+
+        let sight_distance = SIGHT_DISTANCE * board.cell_dimensions.0;
+        let sees_player = nalgebra_glm::distance(&data.position, &player.position)
+            <= sight_distance
+            && line_of_sight(board, &data.position, &player.position);
+
+        let mood = match mood {
+            BunnyMood::Wandering | BunnyMood::Hiding(_) if sees_player => {
+                path = flee_path(&navigator, cell, cell_of(board, &player.position));
+                if path.is_empty() {
+                    // Cornered, or already in the best hiding place.
+                    BunnyMood::Hiding(HIDE_TICKS)
+                } else {
+                    BunnyMood::Fleeing
+                }
+            }
+            BunnyMood::Fleeing if path.is_empty() => BunnyMood::Hiding(HIDE_TICKS),
+            BunnyMood::Hiding(0) => BunnyMood::Wandering,
+            BunnyMood::Hiding(ticks) => BunnyMood::Hiding(ticks - 1),
+            BunnyMood::Wandering if path.is_empty() => {
+                path = wander_path(&navigator, cell);
+                BunnyMood::Wandering
+            }
+            mood => mood,
+        };
+
+        let speed = match mood {
+            BunnyMood::Fleeing => FLEE_SPEED,
+            _ => WANDER_SPEED,
+        } * board.cell_dimensions.0;
+
+        let mut position = data.position;
+        let mut orientation = data.orientation;
+        if let Some(&next) = path.front() {
+            if !navigator.is_walkable(next) {
+                // Something got in the way, like a push-wall.
+                path.clear();
+            } else {
+                let target = cell_center(board, next);
+                let to_target = target - position;
+                let distance = glm::length(&to_target);
+                if distance <= speed {
+                    position = target;
+                    path.pop_front();
+                } else {
+                    position += to_target / distance * speed;
+                }
+                if distance > 0.0 {
+                    orientation = to_target.y.atan2(to_target.x);
+                }
+            }
+        }
+
+        let bunny = LoliBunny {
+            position,
+            orientation,
+            mood,
+            path,
+            ..data
+        };
+        (bunny, None)
+    }
+}
+
+/// A path to a random cell close to `start`.
+fn wander_path(navigator: &Navigator, start: Cell) -> VecDeque<Cell> {
+    let reachable = navigator.reachable(start, WANDER_RADIUS);
+    let targets: Vec<_> = reachable
+        .cells()
+        .into_iter()
+        .filter(|(_, steps)| *steps >= 2)
+        .map(|(cell, _)| cell)
+        .collect();
+
+    targets
+        .choose(&mut rand::thread_rng())
+        .and_then(|&target| reachable.path_to(target))
+        .unwrap_or_default()
+        .into()
+}
+
+/// A path away from the player.
+///
+/// Bunnies only run to places they get to before the player,
+/// and they prefer dead ends because they're good for hiding.
+fn flee_path(navigator: &Navigator, start: Cell, player: Cell) -> VecDeque<Cell> {
+    let reachable = navigator.reachable(start, FLEE_RADIUS);
+    let from_player = navigator.reachable(player, FLEE_RADIUS * 2);
+
+    let target = reachable
+        .cells()
+        .into_iter()
+        .filter_map(|(cell, steps)| {
+            let player_steps = from_player.steps(cell).unwrap_or(usize::MAX);
+            (steps < player_steps).then_some((cell, player_steps))
+        })
+        .max_by_key(|&(cell, player_steps)| (navigator.is_dead_end(cell), player_steps, cell));
+
+    target
+        .and_then(|(target, _)| reachable.path_to(target))
+        .unwrap_or_default()
+        .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{board, player_at};
+
+    fn tick_for(mut bunny: LoliBunny, surroundings: &Surroundings, ticks: usize) -> LoliBunny {
+        for _ in 0..ticks {
+            bunny = LoliBunny::tick(bunny, surroundings).0;
+        }
+        bunny
+    }
+
+    #[test]
+    fn test_bunnies_flee_to_a_dead_end() {
+        let board = board(&["+++++++++", "+       +", "+ +++++ +", "+ +", "+++"]);
+        let player = player_at(Vec2::new(75.0, 15.0));
+        let surroundings = Surroundings {
+            board: &board,
+            player: &player,
+            moon_phase: 0.0,
+        };
+        let bunny = LoliBunny::new(Vec2::new(45.0, 15.0), 0.0, Animation::new(1, 0));
+
+        let bunny = tick_for(bunny, &surroundings, 1);
+        assert_eq!(bunny.mood, BunnyMood::Fleeing);
+
+        let bunny = tick_for(bunny, &surroundings, 200);
+        assert!(matches!(bunny.mood, BunnyMood::Hiding(_)));
+        assert_eq!(cell_of(&board, &bunny.position), (1, 3));
+    }
+
+    #[test]
+    fn test_bunnies_dont_see_through_walls() {
+        let board = board(&["+++++", "+ + +", "+++++"]);
+        let player = player_at(Vec2::new(35.0, 15.0));
+        let surroundings = Surroundings {
+            board: &board,
+            player: &player,
+            moon_phase: 0.0,
+        };
+        let bunny = LoliBunny::new(Vec2::new(15.0, 15.0), 0.0, Animation::new(1, 0));
+
+        let bunny = tick_for(bunny, &surroundings, 1);
+
+        assert_eq!(bunny.mood, BunnyMood::Wandering);
+    }
+
+    #[test]
+    fn test_bunnies_see_through_fences() {
+        let board = board(&["++++++", "+  f +", "++++++"]);
+        let player = player_at(Vec2::new(45.0, 15.0));
+        let surroundings = Surroundings {
+            board: &board,
+            player: &player,
+            moon_phase: 0.0,
+        };
+        let bunny = LoliBunny::new(Vec2::new(25.0, 15.0), 0.0, Animation::new(1, 0));
+
+        let bunny = tick_for(bunny, &surroundings, 1);
+
+        assert_ne!(bunny.mood, BunnyMood::Wandering);
+    }
+
+    #[test]
+    fn test_wandering_bunnies_stay_inside_the_maze() {
+        let board = board(&["+++++++", "+     +", "+ +++ +", "+     +", "+++++++"]);
+        let player = player_at(Vec2::new(-100.0, -100.0));
+        let surroundings = Surroundings {
+            board: &board,
+            player: &player,
+            moon_phase: 0.0,
+        };
+        let start = Vec2::new(15.0, 15.0);
+        let mut bunny = LoliBunny::new(start, 0.0, Animation::new(1, 0));
+
+        let mut farthest: f32 = 0.0;
+        for _ in 0..500 {
+            bunny = LoliBunny::tick(bunny, &surroundings).0;
+            assert!(Navigator::new(&board, Doors::OnlyOpen)
+                .is_walkable(cell_of(&board, &bunny.position)));
+            farthest = farthest.max(nalgebra_glm::distance(&start, &bunny.position));
+        }
+        assert!(farthest >= 20.0);
     }
 }
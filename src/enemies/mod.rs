@@ -1,8 +1,19 @@
-use crate::Message;
+use crate::{Board, Message, Player};
 
+mod hunter;
 mod lolibunny;
+pub use hunter::*;
 pub use lolibunny::*;
 
-trait Entity<State> {
-    fn tick(data: State) -> (State, Option<Message>);
+/// What an entity can see of the game when it thinks.
+pub struct Surroundings<'a> {
+    pub board: &'a Board,
+    pub player: &'a Player,
+    pub moon_phase: f32,
+}
+
+pub trait Entity<State> {
+    /// Advances the entity by one frame,
+    /// it may ask the game to do something with the returned message.
+    fn tick(data: State, surroundings: &Surroundings) -> (State, Option<Message>);
 }
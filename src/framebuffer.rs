@@ -34,6 +34,16 @@ impl std::fmt::Display for PaintPointErrors {
 }
 impl std::error::Error for PaintPointErrors {}
 
+/// How a framebuffer is filtered when it's scaled to a different size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ScaleFilter {
+    /// Every pixel becomes a block of pixels, for a chunky retro look.
+    #[default]
+    Nearest,
+    /// Pixels are blended with their neighbours, for a smoother look.
+    Bilinear,
+}
+
 #[derive(Debug)]
 pub enum GetColorErrors {
     XTooLarge,
@@ -208,6 +218,48 @@ impl Framebuffer {
         self.current_color = new_color.into();
     }
 
+    /// Scales the pixels of the framebuffer to fill `target`,
+    /// a buffer of `width` by `height` pixels.
+    pub fn scale_into(&self, target: &mut [u32], width: usize, height: usize, filter: ScaleFilter) {
+        if self.width == 0 || self.height == 0 {
+            return;
+        }
+
+        let x_ratio = self.width as f32 / width as f32;
+        let y_ratio = self.height as f32 / height as f32;
+        let source = |x: usize, y: usize| Color::from(self.buffer[y * self.width + x]);
+
+        for (y, row) in target.chunks_mut(width).take(height).enumerate() {
+            // The position of the center of the pixel in the framebuffer.
+            let source_y = (y as f32 + 0.5) * y_ratio;
+
+            for (x, pixel) in row.iter_mut().enumerate() {
+                let source_x = (x as f32 + 0.5) * x_ratio;
+
+                let color = match filter {
+                    ScaleFilter::Nearest => source(
+                        (source_x as usize).min(self.width - 1),
+                        (source_y as usize).min(self.height - 1),
+                    ),
+                    ScaleFilter::Bilinear => {
+                        let x = (source_x - 0.5).clamp(0.0, (self.width - 1) as f32);
+                        let y = (source_y - 0.5).clamp(0.0, (self.height - 1) as f32);
+                        let (x0, y0) = (x as usize, y as usize);
+                        let (x1, y1) =
+                            ((x0 + 1).min(self.width - 1), (y0 + 1).min(self.height - 1));
+                        let (tx, ty) = (x.fract(), y.fract());
+
+                        let top = source(x0, y0) * (1.0 - tx) + source(x1, y0) * tx;
+                        let bottom = source(x0, y1) * (1.0 - tx) + source(x1, y1) * tx;
+                        top * (1.0 - ty) + bottom * ty
+                    }
+                };
+
+                *pixel = color.into();
+            }
+        }
+    }
+
     /// Saves the pixel data into a .bmp located in the given `file_path`.
     pub fn save(&self, file_path: &str) -> std::io::Result<()> {
         let Framebuffer {
@@ -220,3 +272,38 @@ impl Framebuffer {
         write_bmp_file(file_path, buffer, *width, *height)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn framebuffer(width: usize, height: usize, buffer: &[u32]) -> Framebuffer {
+        let mut framebuffer = Framebuffer::new(width, height);
+        framebuffer.buffer = buffer.to_vec();
+        framebuffer
+    }
+
+    #[test]
+    fn test_nearest_scaling_repeats_pixels() {
+        let framebuffer = framebuffer(2, 1, &[0x000000, 0xffffff]);
+        let mut target = vec![0; 4 * 2];
+
+        framebuffer.scale_into(&mut target, 4, 2, ScaleFilter::Nearest);
+
+        let row = [0x000000, 0x000000, 0xffffff, 0xffffff];
+        assert_eq!(target, [row, row].concat());
+    }
+
+    #[test]
+    fn test_bilinear_scaling_blends_neighbours() {
+        let framebuffer = framebuffer(2, 1, &[0x000000, 0xc8c8c8]);
+        let mut target = vec![0; 4];
+
+        framebuffer.scale_into(&mut target, 4, 1, ScaleFilter::Bilinear);
+
+        assert_eq!(target[0], 0x000000);
+        assert_eq!(target[1], 0x323232);
+        assert_eq!(target[2], 0x969696);
+        assert_eq!(target[3], 0xc8c8c8);
+    }
+}
@@ -0,0 +1,1278 @@
+use crate::animation::{Animation, AnimationClock};
+use crate::audio::AudioPlayer;
+use crate::campaign::Level;
+use crate::enemies::{Entity, Hunter, LoliBunny, Surroundings};
+use crate::framebuffer::Framebuffer;
+use crate::lighting::{bake_light_map, LightKind, LightSource, LightingSettings, LightingTweak};
+use crate::navigation::{cell_center, cell_of};
+use crate::platform::{Key, Platform};
+use crate::raycaster::{GridTraversal, WallSide};
+use crate::render::{init_render, render};
+use crate::texture::GameTextures;
+use crate::{are_equal, BoardCell, GameStatus, LaunchOptions, Surface, WinCondition, CELL_SIZE};
+use crate::{Board, Door, Exit, Message, Model, Player, PushWall};
+use nalgebra_glm::Vec2;
+use rand::seq::SliceRandom;
+use rand::Rng;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+
+/// How far the player walks every frame, in world units.
+const PLAYER_SPEED: f32 = 5.0;
+const PLAYER_ROTATION_SPEED: f32 = 0.006;
+/// How much the player looks up for every pixel the mouse moves, as a fraction of the screen height.
+const PLAYER_PITCH_SPEED: f32 = 0.002;
+/// How far up or down the player can look, as a fraction of the screen height.
+const MAX_PITCH: f32 = 0.5;
+/// The height of the player's eyes while standing, in world units.
+const STANDING_EYE_HEIGHT: f32 = CELL_SIZE / 2.0;
+/// The height of the player's eyes while crouching, in world units.
+const CROUCHING_EYE_HEIGHT: f32 = CELL_SIZE * 0.3;
+/// How fast the eyes go down or up when crouching or standing up, in world units per frame.
+const CROUCH_SPEED: f32 = 2.0;
+/// The vertical speed at the start of a jump, in world units per frame.
+const JUMP_SPEED: f32 = 2.5;
+/// How much the vertical speed decreases every frame while jumping.
+const GRAVITY: f32 = 0.25;
+/// How far from the player lolibunnies are spawned, in cells.
+const LOLIBUNNY_SPAWN_DISTANCE: f32 = 6.0;
+/// How close the player has to get to a lolibunny to catch it, in world units.
+const CATCH_DISTANCE: f32 = CELL_SIZE * 0.3;
+/// How many ticks every frame of the hop of a lolibunny lasts.
+const LOLIBUNNY_HOP_TICKS: u64 = 8;
+/// The points for every lolibunny caught.
+const LOLIBUNNY_POINTS: u32 = 100;
+/// The points for beating a level right as the moon rises,
+/// they go down as the night goes on.
+const MOONLIGHT_BONUS: f32 = 1000.0;
+
+/// The state of the game loop that isn't part of the model,
+/// like the timers used to debounce the inputs.
+pub struct GameLoop {
+    splash_timer: i32,
+    mode_cooldown_timer: i32,
+    previous_mouse: Option<(f32, f32)>,
+}
+
+impl GameLoop {
+    const SPLASH_DELAY: i32 = 300;
+    const MODE_COOLDOWN: i32 = 5;
+
+    pub fn new() -> Self {
+        GameLoop {
+            splash_timer: 0,
+            mode_cooldown_timer: 0,
+            previous_mouse: None,
+        }
+    }
+
+    /// Runs a single frame: reads the inputs, updates the model with them
+    /// and presents the rendered frame.
+    pub fn frame(
+        &mut self,
+        mut data: Model,
+        framebuffer: &mut Framebuffer,
+        platform: &mut impl Platform,
+    ) -> Model {
+        self.mode_cooldown_timer = (self.mode_cooldown_timer - 1).max(0);
+        self.splash_timer = (self.splash_timer + 1).min(GameLoop::SPLASH_DELAY + 1);
+
+        // listen to inputs
+        for msg in self.read_inputs(&data, platform) {
+            data = update(data, msg);
+        }
+        render(framebuffer, &data);
+        platform.present(framebuffer);
+
+        data
+    }
+
+    fn read_inputs(&mut self, data: &Model, platform: &mut impl Platform) -> Vec<Message> {
+        let mut messages: Vec<Message> = platform
+            .get_keys_pressed()
+            .into_iter()
+            .filter_map(|key| match key {
+                Key::W => match data.status {
+                    GameStatus::Gaming => {
+                        let x_delta = PLAYER_SPEED * data.player.orientation.cos();
+                        let y_delta = PLAYER_SPEED * data.player.orientation.sin();
+                        Some(Message::Move(nalgebra_glm::Vec2::new(x_delta, y_delta)))
+                    }
+                    _ => None,
+                },
+                Key::S => match data.status {
+                    GameStatus::Gaming => {
+                        let x_delta = PLAYER_SPEED * data.player.orientation.cos();
+                        let y_delta = PLAYER_SPEED * data.player.orientation.sin();
+                        Some(Message::Move(nalgebra_glm::Vec2::new(-x_delta, -y_delta)))
+                    }
+                    _ => None,
+                },
+                Key::E => match data.status {
+                    GameStatus::Gaming => Some(Message::Use),
+                    _ => None,
+                },
+                Key::Key1 => Some(Message::TuneLighting(LightingTweak::FogDistance(-20.0))),
+                Key::Key2 => Some(Message::TuneLighting(LightingTweak::FogDistance(20.0))),
+                Key::Key3 => Some(Message::TuneLighting(LightingTweak::LanternRadius(-0.05))),
+                Key::Key4 => Some(Message::TuneLighting(LightingTweak::LanternRadius(0.05))),
+                Key::Key5 => Some(Message::TuneLighting(LightingTweak::Ambient(-0.05))),
+                Key::Key6 => Some(Message::TuneLighting(LightingTweak::Ambient(0.05))),
+                Key::Key7 => Some(Message::TuneLighting(LightingTweak::SideShading(-0.05))),
+                Key::Key8 => Some(Message::TuneLighting(LightingTweak::SideShading(0.05))),
+                Key::Key9 => Some(Message::TuneLighting(LightingTweak::NextFogColor)),
+                Key::A => Some(Message::Rotate(-PLAYER_ROTATION_SPEED * 10.0)),
+                Key::D => Some(Message::Rotate(PLAYER_ROTATION_SPEED * 10.0)),
+                Key::Space => match (self.mode_cooldown_timer, &data.status) {
+                    (0, GameStatus::MainMenu) => {
+                        self.mode_cooldown_timer = GameLoop::MODE_COOLDOWN;
+                        Some(Message::StartGame)
+                    }
+                    (0, GameStatus::LevelComplete) => {
+                        self.mode_cooldown_timer = GameLoop::MODE_COOLDOWN;
+                        Some(Message::NextLevel)
+                    }
+                    (0, GameStatus::Gaming) => Some(Message::Jump),
+                    _ => None,
+                },
+                Key::R => match (self.mode_cooldown_timer, &data.status) {
+                    (0, GameStatus::YouLost) | (0, GameStatus::YouWon) => {
+                        self.mode_cooldown_timer = GameLoop::MODE_COOLDOWN;
+                        Some(Message::RestartGame)
+                    }
+                    _ => None,
+                },
+                Key::P => match (self.mode_cooldown_timer, &data.status) {
+                    (0, GameStatus::Gaming) | (0, GameStatus::Paused) => {
+                        self.mode_cooldown_timer = GameLoop::MODE_COOLDOWN;
+                        Some(Message::TogglePause)
+                    }
+                    _ => None,
+                },
+                _ => None,
+            })
+            .collect();
+        if let GameStatus::Paused = data.status {
+            messages.retain(|msg| matches!(msg, Message::TogglePause));
+        }
+        if self.splash_timer == GameLoop::SPLASH_DELAY {
+            messages.push(Message::EndSplash);
+        }
+        // The screens before and after the game are animated too.
+        if let GameStatus::SplashScreen | GameStatus::YouWon | GameStatus::YouLost = data.status {
+            messages.push(Message::TickAnimations);
+        }
+        if let GameStatus::Gaming = data.status {
+            platform.set_cursor_visibility(false);
+            messages.push(Message::TickMoon);
+            messages.push(Message::TickBoard);
+            messages.push(Message::TickAnimations);
+            messages.push(Message::TickEntities);
+
+            messages.push(Message::TickPlayer);
+            messages.push(Message::Crouch(platform.is_key_down(Key::C)));
+
+            self.previous_mouse = match self.previous_mouse {
+                Some((previous_x, previous_y)) => platform.get_mouse_position().map(|(x, y)| {
+                    let (current_x, current_y) = (x as f32, y as f32);
+                    let delta_x = current_x - previous_x;
+                    let delta_y = current_y - previous_y;
+
+                    messages.push(Message::Rotate(PLAYER_ROTATION_SPEED * delta_x));
+                    messages.push(Message::Pitch(-PLAYER_PITCH_SPEED * delta_y));
+
+                    let (w_width, w_height) = platform.get_size();
+                    let (w_x, w_y) = platform.get_position();
+                    let (w_width, w_height) = (w_width as f32, w_height as f32);
+                    let (w_x, w_y) = (w_x as f32, w_y as f32);
+
+                    // The mouse goes back to the center before it leaves the window.
+                    let leaving_x = current_x < (w_x + 10.0) || current_x > (w_width + w_x - 10.0);
+                    let leaving_y = current_y < (w_y + 10.0) || current_y > (w_height + w_y - 10.0);
+                    if leaving_x || leaving_y {
+                        let x = if leaving_x {
+                            w_width / 2.0 + w_x
+                        } else {
+                            current_x
+                        };
+                        let y = if leaving_y {
+                            w_height / 2.0 + w_y
+                        } else {
+                            current_y
+                        };
+                        platform.move_mouse_to(x as i32, y as i32);
+                        (x, y)
+                    } else {
+                        (current_x, current_y)
+                    }
+                }),
+                None => platform
+                    .get_mouse_position()
+                    .map(|(x, y)| (x as f32, y as f32)),
+            };
+        } else {
+            platform.set_cursor_visibility(true);
+            // The mouse moves freely outside the game, so it doesn't turn the player once back.
+            self.previous_mouse = None;
+        }
+
+        messages
+    }
+}
+
+impl Default for GameLoop {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Runs the game until the platform closes or the player presses escape.
+///
+/// Returns the model as it was on the last frame.
+pub fn run(mut data: Model, framebuffer: &mut Framebuffer, platform: &mut impl Platform) -> Model {
+    let mut game_loop = GameLoop::new();
+    init_render(framebuffer, &data);
+
+    while platform.is_open() && !platform.is_key_down(Key::Escape) {
+        data = game_loop.frame(data, framebuffer, platform);
+    }
+
+    data
+}
+
+/// Init the default state, at the start of the first level of the campaign.
+pub fn init(
+    framebuffer_width: usize,
+    framebuffer_height: usize,
+    launch_options: LaunchOptions,
+) -> Model {
+    init_level(
+        framebuffer_width,
+        framebuffer_height,
+        launch_options,
+        0,
+        0,
+        AnimationClock::default(),
+    )
+}
+
+/// Init the state at the start of a level of the campaign,
+/// carrying over the score and the time of the levels before it.
+pub fn init_level(
+    framebuffer_width: usize,
+    framebuffer_height: usize,
+    launch_options: LaunchOptions,
+    level: usize,
+    score: u32,
+    clock: AnimationClock,
+) -> Model {
+    let muted = &launch_options.muted;
+    let Level {
+        maze_file: file_name,
+        assets_dir,
+        lolibunnies: lolibunny_count,
+        ..
+    } = &launch_options.campaign.levels[level];
+    println!("Reading file name: {}", file_name);
+
+    println!("Loading textures from: {}...", assets_dir);
+    let textures = GameTextures::new(assets_dir);
+
+    println!("Loading audios from: {}...", assets_dir);
+    let audio_player = if *muted {
+        AudioPlayer::muted(assets_dir)
+    } else {
+        AudioPlayer::new(assets_dir)
+    };
+
+    let Maze {
+        board,
+        player_position,
+        empty_cells,
+        hunter_positions,
+        win_condition,
+    } = load_maze(file_name);
+    let (maze_cell_width, maze_cell_height) = board.cell_dimensions;
+
+    let player = Player {
+        position: player_position,
+        orientation: 0.0,
+        fov: std::f32::consts::FRAC_PI_2,
+        pitch: 0.0,
+        eye_height: STANDING_EYE_HEIGHT,
+        vertical_speed: None,
+        crouching: false,
+    };
+
+    assert!(
+        *lolibunny_count > 0,
+        "The level in {} needs at least one lolibunny!",
+        file_name
+    );
+    // Lolibunnies are spawned away from the player, in different cells.
+    let mut rng = rand::thread_rng();
+    let mut spawn_positions: Vec<Vec2> = empty_cells
+        .iter()
+        .map(|&(x, y)| {
+            Vec2::new(
+                x as f32 * maze_cell_width + maze_cell_width / 2.0,
+                y as f32 * maze_cell_height + maze_cell_height / 2.0,
+            )
+        })
+        .filter(|position| {
+            nalgebra_glm::distance(&player_position, position)
+                > LOLIBUNNY_SPAWN_DISTANCE * CELL_SIZE
+        })
+        .collect();
+    assert!(
+        spawn_positions.len() >= *lolibunny_count,
+        "The level in {} asks for {} lolibunnies but there's only room for {}!",
+        file_name,
+        lolibunny_count,
+        spawn_positions.len()
+    );
+    spawn_positions.shuffle(&mut rng);
+
+    let lolibunnies = spawn_positions
+        .into_iter()
+        .take(*lolibunny_count)
+        .map(|position| {
+            LoliBunny::new(
+                position,
+                rng.gen_range(0.0..std::f32::consts::TAU),
+                Animation::new(
+                    LOLIBUNNY_HOP_TICKS,
+                    rng.gen_range(0..LOLIBUNNY_HOP_TICKS * GameTextures::LOLIBUNNY_FRAMES as u64),
+                ),
+            )
+        })
+        .collect();
+
+    Model {
+        board,
+        player,
+        textures,
+        audio_player,
+        lolibunnies,
+        hunters: hunter_positions.into_iter().map(Hunter::new).collect(),
+        framebuffer_dimensions: (framebuffer_width, framebuffer_height),
+        moon_phase: 0.0,
+        clock,
+        final_time: None,
+        status: GameStatus::MainMenu,
+        win_condition,
+        level,
+        score,
+        lighting: LightingSettings::default(),
+        launch_options,
+    }
+}
+
+/// A maze loaded from a file.
+pub struct Maze {
+    pub board: Board,
+    /// Where the player starts, in world coordinates.
+    pub player_position: Vec2,
+    /// The cells where nothing was placed.
+    pub empty_cells: Vec<(usize, usize)>,
+    /// Where hunters start, in world coordinates.
+    pub hunter_positions: Vec<Vec2>,
+    pub win_condition: WinCondition,
+}
+
+/// Reads the maze in `file_name`, every cell is `CELL_SIZE` world units wide.
+pub fn load_maze(file_name: &str) -> Maze {
+    let file = File::open(file_name).expect("Couldn't open maze file!");
+    let reader = BufReader::new(file);
+
+    let mut section = MazeSection::Cells;
+    let mut maze_lines = vec![];
+    let mut floor_lines = vec![];
+    let mut ceiling_lines = vec![];
+    let mut option_lines = vec![];
+    for line in reader.lines() {
+        let line = line.unwrap();
+        match line.trim() {
+            "[floor]" => section = MazeSection::Floor,
+            "[ceiling]" => section = MazeSection::Ceiling,
+            "[options]" => section = MazeSection::Options,
+            _ => match section {
+                MazeSection::Cells => maze_lines.push(line),
+                MazeSection::Floor => floor_lines.push(line),
+                MazeSection::Ceiling => ceiling_lines.push(line),
+                MazeSection::Options => option_lines.push(line),
+            },
+        }
+    }
+
+    let mut empty_cells = vec![];
+    let mut hunter_cells = vec![];
+    let mut lights = vec![];
+    let cells: Vec<Vec<BoardCell>> = maze_lines
+        .iter()
+        .enumerate()
+        .filter_map(|(rowx, line)| match line.trim() {
+            "" => None,
+            not_empty => Some(
+                not_empty
+                    .chars()
+                    .enumerate()
+                    .filter_map(|(colx, c)| {
+                        Some(match c {
+                            '|' => BoardCell::VerticalWall,
+                            '-' => BoardCell::HorizontalWall,
+                            '+' => BoardCell::PillarWall,
+                            'd' => BoardCell::Door(Door::new(WallSide::Horizontal)),
+                            's' => BoardCell::PushWall(PushWall::new()),
+                            'f' => BoardCell::Fence,
+                            'b' => BoardCell::Bars,
+                            't' => {
+                                lights.push(LightSource {
+                                    cell: (colx, rowx),
+                                    kind: LightKind::Torch,
+                                });
+                                BoardCell::Torch
+                            }
+                            'w' => {
+                                lights.push(LightSource {
+                                    cell: (colx, rowx),
+                                    kind: LightKind::Moonlight,
+                                });
+                                BoardCell::Bars
+                            }
+                            'g' => BoardCell::Goal(Exit::new()),
+                            'p' => BoardCell::Player,
+                            'h' => {
+                                hunter_cells.push((colx, rowx));
+                                BoardCell::Empty
+                            }
+                            ' ' => {
+                                empty_cells.push((colx, rowx));
+                                BoardCell::Empty
+                            }
+                            _ => return None,
+                        })
+                    })
+                    .collect(),
+            ),
+        })
+        .collect();
+    let cells = orient_doors(&cells);
+
+    let maze_cell_width = CELL_SIZE;
+    let maze_cell_height = CELL_SIZE;
+
+    let mut player_position = extract_player_starting_position(&cells);
+    player_position.x *= maze_cell_width;
+    player_position.x += maze_cell_width / 2.0;
+
+    player_position.y *= maze_cell_height;
+    player_position.y += maze_cell_height / 2.0;
+
+    let mut board = Board::new(cells, (maze_cell_width, maze_cell_height));
+    for (j, row) in parse_surfaces(&floor_lines).into_iter().enumerate() {
+        for (i, surface) in row.into_iter().enumerate() {
+            if let (Some(surface), Some(floor)) =
+                (surface, board.floors.get_mut(j).and_then(|r| r.get_mut(i)))
+            {
+                *floor = surface;
+            }
+        }
+    }
+    for (j, row) in parse_surfaces(&ceiling_lines).into_iter().enumerate() {
+        for (i, surface) in row.into_iter().enumerate() {
+            if let Some(ceiling) = board.ceilings.get_mut(j).and_then(|r| r.get_mut(i)) {
+                *ceiling = surface;
+            }
+        }
+    }
+    board.lights = lights;
+    board.light_map = bake_light_map(&board);
+    let hunter_positions = hunter_cells
+        .into_iter()
+        .map(|cell| cell_center(&board, cell))
+        .collect();
+
+    // Mazes without an exit can only be won by catching every lolibunny.
+    let has_exit = board
+        .cells
+        .iter()
+        .flatten()
+        .any(|cell| matches!(cell, BoardCell::Goal(_)));
+    let options = parse_options(&option_lines);
+    let win_condition = match options.win {
+        Some(win_condition) => win_condition,
+        None if has_exit => WinCondition::ReachExit,
+        None => WinCondition::CatchBunnies,
+    };
+
+    Maze {
+        board,
+        player_position,
+        empty_cells,
+        hunter_positions,
+        win_condition,
+    }
+}
+
+/// The sections a maze file can contain.
+///
+/// The file starts with the cells of the maze. It can optionally be followed by
+/// a `[floor]` and a `[ceiling]` section, with one character per cell, that
+/// change the texture used for that cell, and an `[options]` section with
+/// one `key = value` setting per line.
+enum MazeSection {
+    Cells,
+    Floor,
+    Ceiling,
+    Options,
+}
+
+/// The settings of the `[options]` section of a maze file,
+/// `None` means the option wasn't set.
+#[derive(Debug, Default, PartialEq)]
+struct MazeOptions {
+    /// `win = exit` or `win = bunnies`.
+    win: Option<WinCondition>,
+}
+
+/// Parses the lines of an `[options]` section, empty lines and `#` comments are skipped.
+fn parse_options(lines: &[String]) -> MazeOptions {
+    let mut options = MazeOptions::default();
+    for line in lines {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let (key, value) = line.split_once('=').unwrap_or_else(|| {
+            panic!("Maze options should look like `key = value`, found `{line}`!")
+        });
+        match (key.trim(), value.trim()) {
+            ("win", "exit") => options.win = Some(WinCondition::ReachExit),
+            ("win", "bunnies") => options.win = Some(WinCondition::CatchBunnies),
+            (key, value) => panic!("Unknown maze option `{key} = {value}`!"),
+        }
+    }
+
+    options
+}
+
+/// Parses the rows of a `[floor]` or `[ceiling]` section.
+/// Every line maps to the maze row with the same index and any character
+/// that isn't a surface leaves the cell with its default.
+fn parse_surfaces(lines: &[String]) -> Vec<Vec<Option<Surface>>> {
+    lines
+        .iter()
+        .map(|line| {
+            line.chars()
+                .map(|c| match c {
+                    '.' => Some(Surface::Grass),
+                    '#' => Some(Surface::Cobblestone),
+                    '=' => Some(Surface::Planks),
+                    _ => None,
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Makes every door span between the walls that surround it.
+pub(crate) fn orient_doors(cells: &[Vec<BoardCell>]) -> Vec<Vec<BoardCell>> {
+    let is_solid = |cell: Option<&BoardCell>| matches!(cell, Some(c) if is_border(c) || matches!(c, BoardCell::Door(_)));
+
+    cells
+        .iter()
+        .map(|row| {
+            row.iter()
+                .enumerate()
+                .map(|(i, cell)| match cell {
+                    BoardCell::Door(_) => {
+                        let left = i.checked_sub(1).and_then(|i| row.get(i));
+                        let right = row.get(i + 1);
+                        let side = if is_solid(left) || is_solid(right) {
+                            WallSide::Horizontal
+                        } else {
+                            WallSide::Vertical
+                        };
+
+                        BoardCell::Door(Door::new(side))
+                    }
+                    cell => cell.clone(),
+                })
+                .collect()
+        })
+        .collect()
+}
+
+fn extract_player_starting_position(cells: &[Vec<BoardCell>]) -> nalgebra_glm::Vec2 {
+    for (j, row) in cells.iter().enumerate() {
+        for (i, cell) in row.iter().enumerate() {
+            if cell == &BoardCell::Player {
+                return nalgebra_glm::Vec2::new(i as f32, j as f32);
+            }
+        }
+    }
+
+    nalgebra_glm::Vec2::zeros()
+}
+
+pub fn is_border(c: &BoardCell) -> bool {
+    matches!(
+        c,
+        BoardCell::VerticalWall
+            | BoardCell::HorizontalWall
+            | BoardCell::PillarWall
+            | BoardCell::Fence
+            | BoardCell::Bars
+            | BoardCell::Torch
+            | BoardCell::Goal(Exit { locked: true })
+    )
+}
+
+pub fn update(data: Model, msg: Message) -> Model {
+    match msg {
+        Message::Move(delta) => {
+            let Model {
+                player,
+                lolibunnies,
+                status,
+                ..
+            } = data;
+            let mut position = player.position + delta;
+
+            let i = (position.x / data.board.cell_dimensions.0) as usize;
+            let j = (position.y / data.board.cell_dimensions.1) as usize;
+
+            let blocked = match &data.board.cells[j][i] {
+                BoardCell::Door(door) => !door.is_passable(),
+                cell => is_border(cell),
+            };
+            // Moving push-walls can be anywhere between two cells.
+            let blocked = blocked
+                || data.board.push_walls_around(i, j).any(|(_, min, max)| {
+                    (min.x..max.x).contains(&position.x) && (min.y..max.y).contains(&position.y)
+                });
+            // Nor can the player step where one of them is going.
+            let blocked = blocked
+                || (cell_of(&data.board, &player.position) != (i, j)
+                    && data.board.is_reserved((i, j)));
+            if blocked {
+                position = player.position;
+            }
+
+            let (lolibunnies, score) = match get_touching_loli(&lolibunnies, &player.position) {
+                Some(idx) => (
+                    lolibunnies
+                        .into_iter()
+                        .enumerate()
+                        .filter(|(i, _)| i != &idx)
+                        .map(|(_, a)| a)
+                        .collect(),
+                    data.score + LOLIBUNNY_POINTS,
+                ),
+                None => (lolibunnies, data.score),
+            };
+
+            let mut board = data.board;
+            if lolibunnies.is_empty() {
+                unlock_exits(&mut board);
+            }
+            let won = match data.win_condition {
+                WinCondition::CatchBunnies => lolibunnies.is_empty(),
+                WinCondition::ReachExit => {
+                    let (i, j) = cell_of(&board, &position);
+                    matches!(&board.cells[j][i], BoardCell::Goal(exit) if exit.is_passable())
+                }
+            };
+
+            let player = Player { position, ..player };
+            let data = Model {
+                board,
+                player,
+                lolibunnies,
+                status,
+                score,
+                ..data
+            };
+            if won {
+                update(data, Message::YouWon)
+            } else {
+                data
+            }
+        }
+        Message::Rotate(delta) => {
+            let Model { player, .. } = data;
+            let orientation = player.orientation + delta;
+            let player = Player {
+                orientation,
+                ..player
+            };
+
+            Model { player, ..data }
+        }
+        Message::Pitch(delta) => {
+            let Model { player, .. } = data;
+            let pitch = (player.pitch + delta).clamp(-MAX_PITCH, MAX_PITCH);
+            let player = Player { pitch, ..player };
+
+            Model { player, ..data }
+        }
+        Message::Jump => {
+            let Model { player, .. } = data;
+            let vertical_speed = match player.vertical_speed {
+                None if !player.crouching => Some(JUMP_SPEED),
+                vertical_speed => vertical_speed,
+            };
+            let player = Player {
+                vertical_speed,
+                ..player
+            };
+
+            Model { player, ..data }
+        }
+        Message::Crouch(crouching) => {
+            let Model { player, .. } = data;
+            let player = Player {
+                crouching,
+                ..player
+            };
+
+            Model { player, ..data }
+        }
+        Message::TickPlayer => {
+            let Model { player, .. } = data;
+            let floor_eye_height = if player.crouching {
+                CROUCHING_EYE_HEIGHT
+            } else {
+                STANDING_EYE_HEIGHT
+            };
+
+            let (eye_height, vertical_speed) = match player.vertical_speed {
+                Some(speed) => {
+                    let eye_height = player.eye_height + speed;
+                    if eye_height <= floor_eye_height {
+                        // Landed.
+                        (floor_eye_height, None)
+                    } else {
+                        (eye_height, Some(speed - GRAVITY))
+                    }
+                }
+                None => {
+                    let delta =
+                        (floor_eye_height - player.eye_height).clamp(-CROUCH_SPEED, CROUCH_SPEED);
+                    (player.eye_height + delta, None)
+                }
+            };
+            let player = Player {
+                eye_height,
+                vertical_speed,
+                ..player
+            };
+
+            Model { player, ..data }
+        }
+        Message::Use => {
+            let (block_width, block_height) = data.board.cell_dimensions;
+            let reach = block_width.max(block_height) * 1.5;
+
+            let faced_step =
+                GridTraversal::new(&data.board, &data.player.position, data.player.orientation)
+                    .take_while(|step| step.distance <= reach)
+                    .find(|step| {
+                        let (i, j) = step.cell;
+                        let cell = &data.board.cells[j][i];
+                        is_border(cell)
+                            || matches!(cell, BoardCell::Door(_) | BoardCell::PushWall(_))
+                    });
+
+            let occupied_cells = occupied_cells(&data);
+            let mut board = data.board;
+            if let Some(step) = faced_step {
+                let (i, j) = step.cell;
+                match &mut board.cells[j][i] {
+                    BoardCell::Door(door) => door.open(),
+                    BoardCell::PushWall(_) => {
+                        // Push-walls slide away from the player.
+                        let orientation = data.player.orientation;
+                        let direction = match step.side {
+                            WallSide::Vertical => (orientation.cos().signum() as isize, 0),
+                            WallSide::Horizontal => (0, orientation.sin().signum() as isize),
+                        };
+                        board.push_wall((i, j), direction, &occupied_cells);
+                    }
+                    _ => {}
+                }
+            }
+
+            Model { board, ..data }
+        }
+        Message::OpenDoor((i, j)) => {
+            let mut board = data.board;
+            if let Some(BoardCell::Door(door)) =
+                board.cells.get_mut(j).and_then(|row| row.get_mut(i))
+            {
+                door.open();
+            }
+
+            Model { board, ..data }
+        }
+        Message::TickBoard => {
+            let occupied_cells = occupied_cells(&data);
+            let mut board = data.board;
+            board.tick(&occupied_cells);
+
+            Model { board, ..data }
+        }
+        Message::TickEntities => {
+            let mut data = data;
+            let surroundings = Surroundings {
+                board: &data.board,
+                player: &data.player,
+                moon_phase: data.moon_phase,
+            };
+            let (lolibunnies, bunny_messages): (Vec<_>, Vec<_>) = data
+                .lolibunnies
+                .drain(..)
+                .map(|bunny| LoliBunny::tick(bunny, &surroundings))
+                .unzip();
+            let (hunters, hunter_messages): (Vec<_>, Vec<_>) = data
+                .hunters
+                .drain(..)
+                .map(|hunter| Hunter::tick(hunter, &surroundings))
+                .unzip();
+            data.lolibunnies = lolibunnies;
+            data.hunters = hunters;
+
+            bunny_messages
+                .into_iter()
+                .chain(hunter_messages)
+                .flatten()
+                .fold(data, update)
+        }
+        Message::TickAnimations => {
+            let mut clock = data.clock;
+            clock.tick();
+
+            Model { clock, ..data }
+        }
+        Message::TogglePause => {
+            let status = match data.status {
+                GameStatus::Gaming => GameStatus::Paused,
+                GameStatus::Paused => GameStatus::Gaming,
+                status => status,
+            };
+
+            Model { status, ..data }
+        }
+        Message::TuneLighting(tweak) => {
+            let mut lighting = data.lighting;
+            lighting.tweak(tweak);
+
+            Model { lighting, ..data }
+        }
+        Message::TickMoon => {
+            // The moon setting on the same frame the player won doesn't take the win away.
+            if !matches!(data.status, GameStatus::Gaming) {
+                return data;
+            }
+            let Model {
+                moon_phase, status, ..
+            } = data;
+
+            let moon_speed = data.launch_options.campaign.levels[data.level].moon_speed;
+            let moon_phase = (moon_phase + moon_speed).min(1.0);
+            let status = if are_equal(moon_phase, 1.0, f32::EPSILON) {
+                data.audio_player.background.sink.skip_one();
+                data.audio_player.loose_song.play();
+                GameStatus::YouLost
+            } else {
+                status
+            };
+
+            Model {
+                moon_phase,
+                status,
+                ..data
+            }
+        }
+        Message::YouWon => {
+            // Only a game that's being played can be won.
+            if !matches!(data.status, GameStatus::Gaming) {
+                return data;
+            }
+            data.audio_player.background.sink.skip_one();
+            data.audio_player.win_song.play();
+
+            let score = data.score + ((1.0 - data.moon_phase) * MOONLIGHT_BONUS) as u32;
+            let is_last_level = data.level + 1 >= data.launch_options.campaign.levels.len();
+            let (status, final_time) = if is_last_level {
+                (GameStatus::YouWon, Some(data.clock))
+            } else {
+                (GameStatus::LevelComplete, None)
+            };
+
+            Model {
+                status,
+                score,
+                final_time,
+                ..data
+            }
+        }
+        Message::NextLevel => {
+            let Model {
+                framebuffer_dimensions,
+                launch_options,
+                level,
+                score,
+                clock,
+                ..
+            } = data;
+            let (framebuffer_width, framebuffer_height) = framebuffer_dimensions;
+
+            let data = init_level(
+                framebuffer_width,
+                framebuffer_height,
+                launch_options,
+                level + 1,
+                score,
+                clock,
+            );
+            data.audio_player.background.play();
+            let status = GameStatus::Gaming;
+
+            Model { status, ..data }
+        }
+        Message::YouLost => {
+            if !matches!(data.status, GameStatus::Gaming) {
+                return data;
+            }
+            data.audio_player.background.sink.skip_one();
+            data.audio_player.loose_song.play();
+            let status = GameStatus::YouLost;
+
+            Model { status, ..data }
+        }
+        Message::RestartGame => {
+            let Model {
+                framebuffer_dimensions,
+                launch_options,
+                ..
+            } = data;
+            let (framebuffer_width, framebuffer_height) = framebuffer_dimensions;
+
+            let data = init(framebuffer_width, framebuffer_height, launch_options);
+            data.audio_player.background.play();
+            data
+        }
+        Message::StartGame => {
+            let status = GameStatus::Gaming;
+
+            Model { status, ..data }
+        }
+        Message::EndSplash => {
+            // The splash screen doesn't count as game time.
+            let status = GameStatus::MainMenu;
+            let clock = AnimationClock::default();
+            Model {
+                status,
+                clock,
+                ..data
+            }
+        }
+    }
+}
+
+/// The cells with someone inside, the player, the lolibunnies or the hunters.
+fn occupied_cells(data: &Model) -> Vec<(usize, usize)> {
+    let bunnies = data.lolibunnies.iter().map(|bunny| bunny.position);
+    let hunters = data.hunters.iter().map(|hunter| hunter.position);
+
+    std::iter::once(data.player.position)
+        .chain(bunnies)
+        .chain(hunters)
+        .map(|position| cell_of(&data.board, &position))
+        .collect()
+}
+
+/// Opens every exit of the board.
+fn unlock_exits(board: &mut Board) {
+    for cell in board.cells.iter_mut().flatten() {
+        if let BoardCell::Goal(exit) = cell {
+            exit.locked = false;
+        }
+    }
+}
+
+fn get_touching_loli(lolis: &[LoliBunny], pos: &Vec2) -> Option<usize> {
+    let bounding_box_size = CATCH_DISTANCE;
+    for (idx, loli) in lolis.iter().enumerate() {
+        if are_equal(pos.x, loli.position.x, bounding_box_size)
+            && are_equal(pos.y, loli.position.y, bounding_box_size)
+        {
+            return Some(idx);
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::campaign::Campaign;
+    use crate::platform::{HeadlessPlatform, ScriptedFrame};
+
+    const WIDTH: usize = 216;
+    const HEIGHT: usize = 144;
+
+    /// A small maze with the exit right next to the player and a couple of lolibunnies.
+    fn launch_options() -> LaunchOptions {
+        let level = Level {
+            lolibunnies: 2,
+            ..Level::new("fixtures/maze", "night_assets/")
+        };
+
+        LaunchOptions {
+            campaign: Campaign::single(level),
+            muted: true,
+        }
+    }
+
+    /// A model of the fixture maze that's already being played.
+    fn test_model() -> Model {
+        let mut data = init(WIDTH, HEIGHT, launch_options());
+        data.status = GameStatus::Gaming;
+        data
+    }
+
+    /// The cell of the exit and the step that takes the player into it.
+    fn step_into_exit(data: &Model) -> ((usize, usize), Vec2) {
+        let exit = data
+            .board
+            .cells
+            .iter()
+            .enumerate()
+            .find_map(|(j, row)| {
+                let i = row
+                    .iter()
+                    .position(|cell| matches!(cell, BoardCell::Goal(_)))?;
+                Some((i, j))
+            })
+            .expect("The fixture maze should have an exit");
+        let step = cell_center(&data.board, exit) - data.player.position;
+        assert_eq!(
+            glm::length(&step),
+            CELL_SIZE,
+            "the exit should be next to the player"
+        );
+
+        (exit, step)
+    }
+
+    #[test]
+    fn test_headless_game_loop_plays_a_script() {
+        let data = init(WIDTH, HEIGHT, launch_options());
+        let start = data.player.position;
+
+        let mut script = vec![ScriptedFrame::new(&[Key::Space])];
+        script.extend((0..5).map(|_| ScriptedFrame::new(&[Key::W])));
+        let mut platform = HeadlessPlatform::new(WIDTH, HEIGHT, script);
+        let mut framebuffer = Framebuffer::new(WIDTH, HEIGHT);
+
+        let data = run(data, &mut framebuffer, &mut platform);
+
+        assert!(matches!(data.status, GameStatus::Gaming));
+        assert!(data.player.position.x > start.x);
+        assert_eq!(platform.frame_count, 6);
+        assert!(platform.framebuffer.buffer == framebuffer.buffer);
+        assert!(framebuffer.buffer.iter().any(|&pixel| pixel != 0));
+    }
+
+    #[test]
+    fn test_pausing_freezes_the_game() {
+        let data = init(WIDTH, HEIGHT, launch_options());
+        let mut framebuffer = Framebuffer::new(WIDTH, HEIGHT);
+
+        let mut script = vec![ScriptedFrame::new(&[Key::Space])];
+        script.extend((0..5).map(|_| ScriptedFrame::new(&[])));
+        script.push(ScriptedFrame::new(&[Key::P]));
+        let mut platform = HeadlessPlatform::new(WIDTH, HEIGHT, script);
+        let data = run(data, &mut framebuffer, &mut platform);
+        assert!(matches!(data.status, GameStatus::Paused));
+        let (clock, moon_phase, position) = (data.clock, data.moon_phase, data.player.position);
+        assert!(clock.ticks > 0);
+
+        let script = (0..5).map(|_| ScriptedFrame::new(&[Key::W])).collect();
+        let mut platform = HeadlessPlatform::new(WIDTH, HEIGHT, script);
+        let data = run(data, &mut framebuffer, &mut platform);
+
+        assert!(matches!(data.status, GameStatus::Paused));
+        assert_eq!(data.clock, clock);
+        assert_eq!(data.moon_phase, moon_phase);
+        assert_eq!(data.player.position, position);
+    }
+
+    #[test]
+    fn test_moving_the_mouse_while_paused_doesnt_turn_the_player() {
+        let mut data = test_model();
+        let mut game_loop = GameLoop::new();
+        let mut platform = HeadlessPlatform::new(WIDTH, HEIGHT, vec![ScriptedFrame::new(&[])]);
+        game_loop.read_inputs(&data, &mut platform);
+
+        data.status = GameStatus::Paused;
+        platform.move_mouse_to(150, 100);
+        game_loop.read_inputs(&data, &mut platform);
+
+        data.status = GameStatus::Gaming;
+        let messages = game_loop.read_inputs(&data, &mut platform);
+
+        assert!(!messages
+            .iter()
+            .any(|message| matches!(message, Message::Rotate(_) | Message::Pitch(_))));
+    }
+
+    #[test]
+    fn test_jumps_go_up_and_land_back() {
+        let mut data = test_model();
+
+        data = update(data, Message::Jump);
+        let mut highest = data.player.eye_height;
+        for _ in 0..100 {
+            data = update(data, Message::TickPlayer);
+            highest = highest.max(data.player.eye_height);
+        }
+
+        assert!(highest > STANDING_EYE_HEIGHT);
+        assert!(highest < CELL_SIZE);
+        assert_eq!(data.player.eye_height, STANDING_EYE_HEIGHT);
+        assert_eq!(data.player.vertical_speed, None);
+    }
+
+    #[test]
+    fn test_getting_caught_loses_the_game() {
+        let mut data = test_model();
+        data.hunters = vec![Hunter::new(data.player.position)];
+
+        let data = update(data, Message::TickEntities);
+
+        assert!(matches!(data.status, GameStatus::YouLost));
+    }
+
+    #[test]
+    fn test_the_exit_opens_after_catching_every_bunny() {
+        let data = test_model();
+        let ((i, j), step) = step_into_exit(&data);
+        let start = cell_of(&data.board, &data.player.position);
+
+        // Locked exits are walls.
+        let data = update(data, Message::Move(step));
+        assert_eq!(cell_of(&data.board, &data.player.position), start);
+        assert!(matches!(data.status, GameStatus::Gaming));
+
+        let data = Model {
+            lolibunnies: vec![],
+            ..data
+        };
+        let data = update(data, Message::Move(Vec2::zeros()));
+        assert!(matches!(data.status, GameStatus::Gaming));
+        assert!(matches!(&data.board.cells[j][i], BoardCell::Goal(exit) if exit.is_passable()));
+
+        let data = update(data, Message::Move(step));
+        assert!(matches!(data.status, GameStatus::YouWon));
+    }
+
+    #[test]
+    fn test_maze_options_choose_how_to_win() {
+        let lines = ["# Catching them is enough", "", "win = bunnies"].map(String::from);
+
+        assert_eq!(parse_options(&[]), MazeOptions::default());
+        assert_eq!(parse_options(&lines).win, Some(WinCondition::CatchBunnies));
+        assert_eq!(
+            parse_options(&["  win=exit ".to_string()]).win,
+            Some(WinCondition::ReachExit)
+        );
+    }
+
+    #[test]
+    fn test_beating_a_level_carries_the_score_to_the_next_one() {
+        let mut launch_options = launch_options();
+        let next_level = Level {
+            lolibunnies: 3,
+            ..launch_options.campaign.levels[0].clone()
+        };
+        launch_options.campaign.levels.push(next_level);
+        let mut data = init(WIDTH, HEIGHT, launch_options);
+        data.status = GameStatus::Gaming;
+        data.score = 300;
+        data.clock.ticks = 1000;
+
+        let data = update(data, Message::YouWon);
+        assert!(matches!(data.status, GameStatus::LevelComplete));
+        assert_eq!(data.score, 300 + MOONLIGHT_BONUS as u32);
+
+        let data = update(data, Message::NextLevel);
+        assert!(matches!(data.status, GameStatus::Gaming));
+        assert_eq!(data.level, 1);
+        assert_eq!(data.score, 300 + MOONLIGHT_BONUS as u32);
+        assert_eq!(data.clock.ticks, 1000);
+        assert_eq!(data.lolibunnies.len(), 3);
+
+        // Beating the last level wins the campaign.
+        let data = update(data, Message::YouWon);
+        assert!(matches!(data.status, GameStatus::YouWon));
+    }
+
+    #[test]
+    fn test_the_win_screen_doesnt_count_as_game_time() {
+        let mut data = test_model();
+        data.clock.ticks = 600;
+
+        let mut data = update(data, Message::YouWon);
+        for _ in 0..10 {
+            data = update(data, Message::TickAnimations);
+        }
+
+        assert!(matches!(data.status, GameStatus::YouWon));
+        assert_eq!(data.clock.ticks, 610);
+        assert_eq!(data.final_time, Some(AnimationClock { ticks: 600 }));
+    }
+
+    #[test]
+    fn test_the_moon_setting_after_a_win_doesnt_lose_it() {
+        let mut data = test_model();
+        data.moon_phase = 1.0;
+
+        let data = update(data, Message::YouWon);
+        let data = update(data, Message::TickMoon);
+
+        assert!(matches!(data.status, GameStatus::YouWon));
+        assert_eq!(data.moon_phase, 1.0);
+    }
+
+    #[test]
+    fn test_losing_players_cant_win_anymore() {
+        let data = test_model();
+        let (_, step) = step_into_exit(&data);
+        let data = update(data, Message::YouLost);
+        let data = Model {
+            lolibunnies: vec![],
+            ..data
+        };
+
+        let data = update(data, Message::Move(Vec2::zeros()));
+        let data = update(data, Message::Move(step));
+
+        assert!(matches!(data.status, GameStatus::YouLost));
+        assert_eq!(data.score, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "asks for 500 lolibunnies")]
+    fn test_levels_without_room_for_their_bunnies_fail() {
+        let mut launch_options = launch_options();
+        launch_options.campaign.levels[0].lolibunnies = 500;
+
+        init(WIDTH, HEIGHT, launch_options);
+    }
+}
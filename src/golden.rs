@@ -0,0 +1,270 @@
+//! Golden-image tests for the renderer.
+//!
+//! Every test renders a fixed scene of `golden/maze` and compares it against
+//! a reference image in `golden/`. When a test fails the rendered frame and an
+//! image with the differences painted red are written to `target/golden/`.
+//!
+//! After an intended change to the renderer, run the tests with `UPDATE_GOLDEN=1`
+//! to write the reference images again.
+
+use std::{env, fs};
+
+use nalgebra_glm::Vec2;
+
+use crate::{
+    animation::{Animation, AnimationClock},
+    audio::AudioPlayer,
+    bmp::{read_bmp_file, write_bmp_file},
+    campaign::{Campaign, Level},
+    color::Color,
+    enemies::{Hunter, LoliBunny},
+    framebuffer::Framebuffer,
+    game::{load_maze, Maze},
+    lighting::LightingSettings,
+    render::render,
+    texture::GameTextures,
+    BoardCell, Exit, GameStatus, LaunchOptions, Model, Player, WinCondition,
+};
+
+const MAZE_FILE: &str = "golden/maze";
+const ASSETS_DIR: &str = "night_assets/";
+const GOLDEN_DIR: &str = "golden/";
+const OUTPUT_DIR: &str = "target/golden/";
+const WIDTH: usize = 160;
+const HEIGHT: usize = 120;
+/// How much a color channel can change before the pixel counts as different.
+const TOLERANCE: u8 = 3;
+
+/// Creates the model of a scene of the golden maze.
+/// Positions are given in cells, so they don't depend on the size of the framebuffer.
+fn scene(position: (f32, f32), orientation: f32, lolibunnies: &[(f32, f32)]) -> Model {
+    let Maze { board, .. } = load_maze(MAZE_FILE);
+    let (block_width, block_height) = board.cell_dimensions;
+    let to_world = |(i, j): (f32, f32)| Vec2::new(i * block_width, j * block_height);
+
+    Model {
+        player: Player {
+            position: to_world(position),
+            orientation,
+            fov: std::f32::consts::FRAC_PI_2,
+            pitch: 0.0,
+            eye_height: block_height / 2.0,
+            vertical_speed: None,
+            crouching: false,
+        },
+        lolibunnies: lolibunnies
+            .iter()
+            .map(|&position| {
+                // Looking back at the player.
+                LoliBunny::new(
+                    to_world(position),
+                    std::f32::consts::PI,
+                    Animation::new(1, 0),
+                )
+            })
+            .collect(),
+        hunters: vec![],
+        board,
+        framebuffer_dimensions: (WIDTH, HEIGHT),
+        textures: GameTextures::new(ASSETS_DIR),
+        audio_player: AudioPlayer::muted(ASSETS_DIR),
+        moon_phase: 0.3,
+        clock: AnimationClock::default(),
+        final_time: None,
+        status: GameStatus::Gaming,
+        win_condition: WinCondition::ReachExit,
+        level: 0,
+        score: 0,
+        lighting: LightingSettings::default(),
+        launch_options: LaunchOptions {
+            campaign: Campaign::single(Level::new(MAZE_FILE, ASSETS_DIR)),
+            muted: true,
+        },
+    }
+}
+
+/// Renders the model and compares the frame against the golden image called `name`.
+fn assert_matches_golden(name: &str, data: &Model) {
+    let mut framebuffer = Framebuffer::new(WIDTH, HEIGHT);
+    render(&mut framebuffer, data);
+
+    let golden_path = format!("{}{}.bmp", GOLDEN_DIR, name);
+    if env::var_os("UPDATE_GOLDEN").is_some() {
+        framebuffer.save(&golden_path).unwrap();
+        return;
+    }
+
+    let golden = read_bmp_file(&golden_path)
+        .unwrap_or_else(|_| panic!("Couldn't read {}, run with UPDATE_GOLDEN=1", golden_path));
+    assert_eq!(
+        (golden.width, golden.height),
+        (WIDTH, HEIGHT),
+        "{} has the wrong size",
+        golden_path
+    );
+
+    let mut mismatches = 0;
+    let diff: Vec<u32> = framebuffer
+        .buffer
+        .iter()
+        .zip(&golden.buffer)
+        .map(|(&actual, expected)| {
+            let actual: Color = actual.into();
+            let expected: Color = expected.into();
+            let matches = [
+                (actual.r, expected.r),
+                (actual.g, expected.g),
+                (actual.b, expected.b),
+            ]
+            .into_iter()
+            .all(|(a, e)| a.abs_diff(e) <= TOLERANCE);
+
+            if matches {
+                (actual * 0.25).into()
+            } else {
+                mismatches += 1;
+                0xff0000
+            }
+        })
+        .collect();
+
+    if mismatches > 0 {
+        fs::create_dir_all(OUTPUT_DIR).unwrap();
+        let actual_path = format!("{}{}.bmp", OUTPUT_DIR, name);
+        let diff_path = format!("{}{}.diff.bmp", OUTPUT_DIR, name);
+        framebuffer.save(&actual_path).unwrap();
+        write_bmp_file(&diff_path, &diff, WIDTH, HEIGHT).unwrap();
+
+        panic!(
+            "{} pixels differ from {}, see {} and {}",
+            mismatches, golden_path, actual_path, diff_path
+        );
+    }
+}
+
+#[test]
+fn test_golden_corridor() {
+    let data = scene((1.5, 1.5), 0.0, &[]);
+
+    assert_matches_golden("corridor", &data);
+}
+
+#[test]
+fn test_golden_torch() {
+    let data = scene((4.5, 3.5), 0.2, &[]);
+
+    assert_matches_golden("torch", &data);
+}
+
+#[test]
+fn test_golden_exit() {
+    let mut data = scene((11.5, 3.5), 0.0, &[]);
+    data.board.cells[3][14] = BoardCell::Goal(Exit::new());
+
+    assert_matches_golden("exit_locked", &data);
+
+    if let BoardCell::Goal(exit) = &mut data.board.cells[3][14] {
+        exit.locked = false;
+    }
+    assert_matches_golden("exit_open", &data);
+}
+
+#[test]
+fn test_golden_lolibunnies() {
+    let data = scene(
+        (1.5, 1.5),
+        0.1,
+        &[(4.5, 1.5), (8.0, 1.2), (12.5, 1.8), (13.5, 2.5)],
+    );
+
+    assert_matches_golden("lolibunnies", &data);
+}
+
+#[test]
+fn test_golden_sprites_on_the_screen_edges() {
+    // The nearest bunny sticks out of the top and the left of the screen,
+    // the others overlap each other and hide behind the walls.
+    let data = scene(
+        (3.0, 1.5),
+        0.0,
+        &[(3.6, 1.15), (5.0, 1.9), (6.0, 1.5), (7.0, 1.6)],
+    );
+
+    assert_matches_golden("sprites_on_the_screen_edges", &data);
+}
+
+#[test]
+fn test_golden_bunnies_looking_around() {
+    let mut data = scene(
+        (1.5, 1.5),
+        0.0,
+        &[(2.8, 1.15), (2.8, 1.85), (5.0, 1.6), (7.0, 1.2)],
+    );
+    // Looking to the player, away from it and to each side.
+    let orientations = [
+        std::f32::consts::PI,
+        0.0,
+        std::f32::consts::FRAC_PI_2,
+        -std::f32::consts::FRAC_PI_2,
+    ];
+    for (bunny, orientation) in data.lolibunnies.iter_mut().zip(orientations) {
+        bunny.orientation = orientation;
+    }
+
+    assert_matches_golden("bunnies_looking_around", &data);
+}
+
+#[test]
+fn test_golden_looking_up_while_crouching() {
+    let mut data = scene((1.5, 1.5), 0.1, &[(4.5, 1.5)]);
+    data.player.pitch = 0.2;
+    data.player.eye_height = data.board.cell_dimensions.1 * 0.3;
+    data.player.crouching = true;
+
+    assert_matches_golden("looking_up_while_crouching", &data);
+}
+
+#[test]
+fn test_golden_looking_down_in_the_air() {
+    let mut data = scene((4.5, 3.5), 0.2, &[]);
+    data.player.pitch = -0.25;
+    data.player.eye_height = data.board.cell_dimensions.1 * 0.7;
+
+    assert_matches_golden("looking_down_in_the_air", &data);
+}
+
+#[test]
+fn test_golden_hunter() {
+    let mut data = scene((1.5, 1.5), 0.0, &[(5.0, 1.3)]);
+    let (block_width, block_height) = data.board.cell_dimensions;
+    data.hunters = vec![Hunter::new(Vec2::new(
+        3.5 * block_width,
+        1.6 * block_height,
+    ))];
+
+    assert_matches_golden("hunter", &data);
+}
+
+#[test]
+fn test_golden_level_complete() {
+    let mut data = scene((1.5, 1.5), 0.0, &[]);
+    data.status = GameStatus::LevelComplete;
+    data.score = 1850;
+    data.clock.ticks = 187 * AnimationClock::TICKS_PER_SECOND;
+
+    assert_matches_golden("level_complete", &data);
+}
+
+#[test]
+fn test_golden_you_won() {
+    let mut data = scene((1.5, 1.5), 0.0, &[]);
+    data.status = GameStatus::YouWon;
+    data.score = 4200;
+    data.final_time = Some(AnimationClock {
+        ticks: 187 * AnimationClock::TICKS_PER_SECOND,
+    });
+    // Some frames into the animation of the win screen.
+    data.clock.ticks = data.final_time.unwrap().ticks + 10;
+
+    assert_matches_golden("you_won", &data);
+}
@@ -1,17 +1,37 @@
+use animation::AnimationClock;
 use audio::AudioPlayer;
+use campaign::Campaign;
+use glm::Vec2;
+use lighting::{LightSource, LightingSettings, LightingTweak};
+use raycaster::WallSide;
 use texture::GameTextures;
 
+pub mod animation;
 pub mod audio;
 pub mod bmp;
+pub mod campaign;
 pub mod color;
 pub mod enemies;
 pub mod framebuffer;
+pub mod game;
+#[cfg(test)]
+mod golden;
+pub mod lighting;
+pub mod navigation;
+pub mod platform;
 pub mod raycaster;
 pub mod render;
+pub mod sprite;
+#[cfg(test)]
+pub(crate) mod test_support;
+pub mod text;
 pub mod texture;
 
 extern crate nalgebra_glm as glm;
 
+/// The width and depth of every cell of a maze in world units, and the height of its walls.
+pub const CELL_SIZE: f32 = 64.0;
+
 pub fn are_equal(first: f32, second: f32, eps: f32) -> bool {
     (first - second).abs() <= eps
 }
@@ -20,16 +40,358 @@ pub fn are_equal(first: f32, second: f32, eps: f32) -> bool {
 pub enum BoardCell {
     Empty,
     Player,
-    Goal,
+    /// The way out of the maze.
+    Goal(Exit),
     LoliBunny(enemies::LoliBunny),
     HorizontalWall,
     VerticalWall,
     PillarWall,
+    Door(Door),
+    PushWall(PushWall),
+    /// A wooden fence that can be seen through.
+    Fence,
+    /// Iron bars that can be seen through, for cages and windows.
+    Bars,
+    /// A wall with a torch hanging from it.
+    Torch,
+}
+
+impl BoardCell {
+    /// Whether rays keep going after hitting this cell, so what's behind it can be seen.
+    pub fn is_see_through(&self) -> bool {
+        match self {
+            BoardCell::Fence | BoardCell::Bars => true,
+            BoardCell::Goal(exit) => exit.is_passable(),
+            _ => false,
+        }
+    }
+}
+
+/// The gate out of the maze, it stays locked until every lolibunny is caught.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Exit {
+    pub locked: bool,
+}
+
+impl Exit {
+    pub fn new() -> Self {
+        Exit { locked: true }
+    }
+
+    /// Whether the player can walk through the gate.
+    pub fn is_passable(&self) -> bool {
+        !self.locked
+    }
+}
+
+impl Default for Exit {
+    fn default() -> Self {
+        Exit::new()
+    }
+}
+
+/// How a maze is won, it's set with the `win` option of the maze file.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WinCondition {
+    /// Catching every lolibunny wins right away.
+    CatchBunnies,
+    /// Catching every lolibunny unlocks the exits and going through one wins.
+    ReachExit,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum DoorState {
+    Closed,
+    Opening,
+    Open,
+}
+
+/// A sliding door recessed into the middle of its cell.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Door {
+    pub state: DoorState,
+    /// How far the door has slid into the wall,
+    /// `0.0` means it's closed and `1.0` means it's completely open.
+    pub open_fraction: f32,
+    /// The face rays hit the door from.
+    /// A `WallSide::Horizontal` door spans its cell from west to east.
+    pub side: WallSide,
+}
+
+impl Door {
+    /// How much the door opens every time the board ticks.
+    const OPENING_SPEED: f32 = 0.02;
+    /// How open the door needs to be for the player to go through it.
+    const PASSABLE_FRACTION: f32 = 0.75;
+
+    pub fn new(side: WallSide) -> Self {
+        Door {
+            state: DoorState::Closed,
+            open_fraction: 0.0,
+            side,
+        }
+    }
+
+    pub fn is_passable(&self) -> bool {
+        self.open_fraction >= Door::PASSABLE_FRACTION
+    }
+
+    /// Starts opening the door if it's closed.
+    pub fn open(&mut self) {
+        if self.state == DoorState::Closed {
+            self.state = DoorState::Opening;
+        }
+    }
+
+    /// Advances the door animation by one frame.
+    pub fn tick(&mut self) {
+        if self.state == DoorState::Opening {
+            self.open_fraction = (self.open_fraction + Door::OPENING_SPEED).min(1.0);
+            if self.open_fraction >= 1.0 {
+                self.state = DoorState::Open;
+            }
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum PushWallState {
+    Idle,
+    Moving,
+    Done,
+}
+
+/// A secret wall that looks like any other but slides back when used,
+/// revealing the passage hidden behind it.
+///
+/// While it moves the wall is stored in the cell it's leaving and
+/// overlaps the next one by `offset`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct PushWall {
+    pub state: PushWallState,
+    /// How far into the next cell the wall has slid, from `0.0` to `1.0`.
+    pub offset: f32,
+    /// The direction the wall moves to, in cells.
+    pub direction: (isize, isize),
+    /// How many cells the wall still has to move.
+    pub cells_left: usize,
+}
+
+impl PushWall {
+    /// How many cells a push-wall slides back.
+    pub const DISTANCE: usize = 2;
+    /// How much the wall moves every time the board ticks.
+    const SPEED: f32 = 0.025;
+
+    pub fn new() -> Self {
+        PushWall {
+            state: PushWallState::Idle,
+            offset: 0.0,
+            direction: (0, 0),
+            cells_left: 0,
+        }
+    }
+}
+
+impl Default for PushWall {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The texture used to paint the floor or the ceiling of a cell.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Surface {
+    Grass,
+    Cobblestone,
+    Planks,
 }
 
 pub struct Board {
     pub cells: Vec<Vec<BoardCell>>,
     pub cell_dimensions: (f32, f32),
+    /// The floor of every cell.
+    pub floors: Vec<Vec<Surface>>,
+    /// The ceiling of every cell, `None` means the cell is open to the sky.
+    pub ceilings: Vec<Vec<Option<Surface>>>,
+    /// The lights placed on the maze.
+    pub lights: Vec<LightSource>,
+    /// The light level of every cell, baked from `lights` when the maze is loaded.
+    pub light_map: Vec<Vec<f32>>,
+}
+
+impl Board {
+    /// Creates a board with grass floors, no ceilings and no lights.
+    pub fn new(cells: Vec<Vec<BoardCell>>, cell_dimensions: (f32, f32)) -> Self {
+        let floors = cells
+            .iter()
+            .map(|row| vec![Surface::Grass; row.len()])
+            .collect();
+        let ceilings = cells.iter().map(|row| vec![None; row.len()]).collect();
+        let light_map = cells.iter().map(|row| vec![0.0; row.len()]).collect();
+
+        Board {
+            cells,
+            cell_dimensions,
+            floors,
+            ceilings,
+            lights: vec![],
+            light_map,
+        }
+    }
+
+    /// The baked light level at the given point of the world.
+    pub fn light_at(&self, point: &Vec2) -> f32 {
+        let (block_width, block_height) = self.cell_dimensions;
+        let i = (point.x / block_width).floor();
+        let j = (point.y / block_height).floor();
+        if i < 0.0 || j < 0.0 {
+            return 0.0;
+        }
+
+        self.light_map
+            .get(j as usize)
+            .and_then(|row| row.get(i as usize))
+            .copied()
+            .unwrap_or(0.0)
+    }
+
+    /// Starts sliding the push-wall at `(i, j)` towards `direction`,
+    /// as far as `PushWall::DISTANCE` cells if they're empty.
+    ///
+    /// `occupied` are the cells with someone inside, like the player or the lolibunnies,
+    /// the wall stops before them so nobody gets buried.
+    pub fn push_wall(
+        &mut self,
+        (i, j): (usize, usize),
+        direction: (isize, isize),
+        occupied: &[(usize, usize)],
+    ) {
+        let cells_left = (1..=PushWall::DISTANCE as isize)
+            .take_while(|&n| {
+                let next_i = i as isize + direction.0 * n;
+                let next_j = j as isize + direction.1 * n;
+                self.cell(next_i, next_j) == Some(&BoardCell::Empty)
+                    && !occupied.contains(&(next_i as usize, next_j as usize))
+            })
+            .count();
+
+        if let Some(BoardCell::PushWall(wall)) = self.cells.get_mut(j).and_then(|r| r.get_mut(i)) {
+            if wall.state == PushWallState::Idle && cells_left > 0 {
+                wall.state = PushWallState::Moving;
+                wall.direction = direction;
+                wall.cells_left = cells_left;
+            }
+        }
+    }
+
+    /// The push-wall stored at `(i, j)` with the area it covers,
+    /// given as its top left and bottom right corners.
+    pub fn push_wall_bounds(&self, i: isize, j: isize) -> Option<(&PushWall, Vec2, Vec2)> {
+        let wall = match self.cell(i, j) {
+            Some(BoardCell::PushWall(wall)) => wall,
+            _ => return None,
+        };
+
+        let (block_width, block_height) = self.cell_dimensions;
+        let min = Vec2::new(
+            (i as f32 + wall.direction.0 as f32 * wall.offset) * block_width,
+            (j as f32 + wall.direction.1 as f32 * wall.offset) * block_height,
+        );
+        let max = min + Vec2::new(block_width, block_height);
+
+        Some((wall, min, max))
+    }
+
+    /// The push-walls that may overlap the cell `(i, j)`,
+    /// that is the one inside it and the ones moving from its neighbours.
+    pub fn push_walls_around(
+        &self,
+        i: usize,
+        j: usize,
+    ) -> impl Iterator<Item = (&PushWall, Vec2, Vec2)> + '_ {
+        let (i, j) = (i as isize, j as isize);
+        [(0, 0), (-1, 0), (1, 0), (0, -1), (0, 1)]
+            .into_iter()
+            .filter_map(move |(di, dj)| self.push_wall_bounds(i + di, j + dj))
+    }
+
+    /// Whether a push-wall is sliding into the cell, so nobody should go in.
+    pub fn is_reserved(&self, (i, j): (usize, usize)) -> bool {
+        let (i, j) = (i as isize, j as isize);
+        [(-1, 0), (1, 0), (0, -1), (0, 1)]
+            .into_iter()
+            .any(|(di, dj)| match self.cell(i + di, j + dj) {
+                Some(BoardCell::PushWall(wall)) => {
+                    wall.state == PushWallState::Moving && wall.direction == (-di, -dj)
+                }
+                _ => false,
+            })
+    }
+
+    /// Advances the animations of doors and push-walls by one frame.
+    ///
+    /// `occupied` are the cells with someone inside, moving push-walls stop
+    /// instead of going on into them.
+    pub fn tick(&mut self, occupied: &[(usize, usize)]) {
+        let mut crossed = vec![];
+        for (j, row) in self.cells.iter_mut().enumerate() {
+            for (i, cell) in row.iter_mut().enumerate() {
+                match cell {
+                    BoardCell::Door(door) => door.tick(),
+                    BoardCell::PushWall(wall) if wall.state == PushWallState::Moving => {
+                        wall.offset += PushWall::SPEED;
+                        if wall.offset >= 1.0 {
+                            crossed.push((i, j));
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        // Walls that made it into the next cell are moved there.
+        let mut settled = false;
+        for (i, j) in crossed {
+            let mut wall = match std::mem::replace(&mut self.cells[j][i], BoardCell::Empty) {
+                BoardCell::PushWall(wall) => wall,
+                _ => unreachable!(),
+            };
+
+            wall.offset -= 1.0;
+            wall.cells_left -= 1;
+
+            let next_i = (i as isize + wall.direction.0) as usize;
+            let next_j = (j as isize + wall.direction.1) as usize;
+            let after = (
+                (next_i as isize + wall.direction.0) as usize,
+                (next_j as isize + wall.direction.1) as usize,
+            );
+            if wall.cells_left == 0 || occupied.contains(&after) {
+                wall.offset = 0.0;
+                wall.cells_left = 0;
+                wall.state = PushWallState::Done;
+                settled = true;
+            }
+
+            self.cells[next_j][next_i] = BoardCell::PushWall(wall);
+        }
+
+        // The light spreads differently around the wall's new place.
+        if settled {
+            self.light_map = lighting::bake_light_map(self);
+        }
+    }
+
+    fn cell(&self, i: isize, j: isize) -> Option<&BoardCell> {
+        if i < 0 || j < 0 {
+            return None;
+        }
+
+        self.cells
+            .get(j as usize)
+            .and_then(|row| row.get(i as usize))
+    }
 }
 
 pub struct Model {
@@ -39,15 +401,40 @@ pub struct Model {
     pub textures: GameTextures,
     pub audio_player: AudioPlayer,
     pub lolibunnies: Vec<enemies::LoliBunny>,
+    pub hunters: Vec<enemies::Hunter>,
     pub moon_phase: f32,
+    /// The game time, it doesn't move while the game is paused.
+    pub clock: AnimationClock,
+    /// The game time the campaign was won at, the clock keeps going for the win screen.
+    pub final_time: Option<AnimationClock>,
     pub status: GameStatus,
+    pub win_condition: WinCondition,
+    /// The index of the level being played in the campaign.
+    pub level: usize,
+    /// The points earned through the whole campaign.
+    pub score: u32,
+    pub lighting: LightingSettings,
+    pub launch_options: LaunchOptions,
+}
+
+/// What the game was started with, so it can be started again.
+#[derive(Debug, Clone)]
+pub struct LaunchOptions {
+    pub campaign: Campaign,
+    /// Plays without sound, so no audio device is needed.
+    pub muted: bool,
 }
 
 pub enum GameStatus {
     SplashScreen,
     MainMenu,
     Gaming,
+    /// Everything is frozen until the game is resumed.
+    Paused,
     YouLost,
+    /// A level was beaten and the next one is waiting.
+    LevelComplete,
+    /// The last level of the campaign was beaten.
     YouWon,
 }
 
@@ -55,15 +442,110 @@ pub struct Player {
     pub position: nalgebra_glm::Vec2,
     pub orientation: f32,
     pub fov: f32,
+    /// How far up the player looks, as a fraction of the screen height.
+    /// Looking up moves the horizon down the screen.
+    pub pitch: f32,
+    /// The height of the player's eyes from the floor in world units.
+    pub eye_height: f32,
+    /// How fast the player goes up while jumping, in world units per frame.
+    /// It's `None` while they're standing on the floor.
+    pub vertical_speed: Option<f32>,
+    pub crouching: bool,
 }
 
 pub enum Message {
     Move(nalgebra_glm::Vec2),
     Rotate(f32),
+    /// Looks up or down, as a fraction of the screen height.
+    Pitch(f32),
+    Jump,
+    Crouch(bool),
+    /// Advances the jumps and crouches of the player.
+    TickPlayer,
+    /// Interacts with whatever the player is facing, like opening a door.
+    Use,
+    /// Opens the door in the given cell, if it's closed.
+    OpenDoor((usize, usize)),
     TickMoon,
+    /// Advances the animations of the board cells, like opening doors.
+    TickBoard,
+    /// Lets every entity think and move.
+    TickEntities,
+    /// Advances the game time the animations follow.
+    TickAnimations,
+    /// Pauses the game or resumes it.
+    TogglePause,
+    TuneLighting(LightingTweak),
     EndSplash,
     RestartGame,
+    /// Starts the level after the current one, keeping the score and the time.
+    NextLevel,
     YouWon,
     YouLost,
     StartGame,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lighting::{bake_light_map, LightKind};
+    use test_support::board;
+
+    /// A corridor with a push-wall at its west end.
+    const CORRIDOR: [&str; 3] = ["+++++++", "+s    +", "+++++++"];
+
+    fn tick_for(board: &mut Board, occupied: &[(usize, usize)], ticks: usize) {
+        for _ in 0..ticks {
+            board.tick(occupied);
+        }
+    }
+
+    #[test]
+    fn test_push_walls_dont_bury_anyone() {
+        let mut board = board(&CORRIDOR);
+        board.push_wall((1, 1), (1, 0), &[(2, 1)]);
+
+        // Someone is right behind the wall.
+        assert!(board.cells[1][1] == BoardCell::PushWall(PushWall::new()));
+
+        board.push_wall((1, 1), (1, 0), &[(3, 1)]);
+        tick_for(&mut board, &[(3, 1)], 200);
+
+        assert!(matches!(
+            &board.cells[1][2],
+            BoardCell::PushWall(wall) if wall.state == PushWallState::Done
+        ));
+        assert!(board.cells[1][3] == BoardCell::Empty);
+    }
+
+    #[test]
+    fn test_push_walls_stop_when_someone_gets_in_the_way() {
+        let mut board = board(&CORRIDOR);
+        board.push_wall((1, 1), (1, 0), &[]);
+        assert!(board.is_reserved((2, 1)));
+
+        tick_for(&mut board, &[(3, 1)], 200);
+
+        assert!(matches!(&board.cells[1][2], BoardCell::PushWall(_)));
+        assert!(!board.is_reserved((3, 1)));
+    }
+
+    #[test]
+    fn test_the_light_is_baked_again_when_push_walls_stop() {
+        let mut board = board(&CORRIDOR);
+        board.lights = vec![LightSource {
+            cell: (5, 1),
+            kind: LightKind::Torch,
+        }];
+        board.light_map = bake_light_map(&board);
+        assert!(board.light_map[1][2] > 0.0);
+
+        board.push_wall((1, 1), (1, 0), &[]);
+        tick_for(&mut board, &[], 200);
+
+        assert!(matches!(&board.cells[1][3], BoardCell::PushWall(_)));
+        assert_eq!(board.light_map, bake_light_map(&board));
+        // The cell the wall left is now in its shadow.
+        assert_eq!(board.light_map[1][2], 0.0);
+    }
+}
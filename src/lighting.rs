@@ -0,0 +1,332 @@
+use std::collections::VecDeque;
+
+use crate::{color::Color, raycaster::WallSide, Board, BoardCell};
+
+/// The parameters of the lighting model, they can be tuned while playing.
+#[derive(Debug, Clone)]
+pub struct LightingSettings {
+    /// The color everything fades into as it gets farther away.
+    pub fog_color: Color,
+    /// The distance, in world units, where the fog starts.
+    pub fog_start: f32,
+    /// The distance, in world units, where the fog completely hides everything.
+    pub fog_end: f32,
+    /// Radius of the fully lit circle of the lantern, as a fraction of half the screen width.
+    pub lantern_radius: f32,
+    /// How long it takes the lantern light to fade out, as a fraction of half the screen width.
+    pub lantern_falloff: f32,
+    /// The brightness of everything outside of the lantern light, from `0.0` to `1.0`.
+    pub ambient: f32,
+    /// The brightness of east and west faces compared to north and south faces.
+    pub side_shading: f32,
+}
+
+/// The kinds of lights that can be placed on the maze.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum LightKind {
+    /// A torch hanging from a wall.
+    Torch,
+    /// The moonlight coming in through a window.
+    Moonlight,
+}
+
+impl LightKind {
+    /// The light level right next to the source.
+    pub fn intensity(&self) -> f32 {
+        match self {
+            LightKind::Torch => 1.0,
+            LightKind::Moonlight => 0.6,
+        }
+    }
+
+    /// How many cells away the light reaches.
+    pub fn radius(&self) -> usize {
+        match self {
+            LightKind::Torch => 6,
+            LightKind::Moonlight => 4,
+        }
+    }
+}
+
+/// A light placed on the cell `(i, j)` of the board.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct LightSource {
+    pub cell: (usize, usize),
+    pub kind: LightKind,
+}
+
+/// The fog colors that can be picked while playing, the first one is the default.
+const FOG_COLORS: [(u8, u8, u8); 4] = [(0, 2, 12), (0, 0, 0), (24, 24, 30), (28, 6, 16)];
+
+/// A change to one of the `LightingSettings`.
+#[derive(Debug, Clone, Copy)]
+pub enum LightingTweak {
+    FogDistance(f32),
+    LanternRadius(f32),
+    Ambient(f32),
+    SideShading(f32),
+    /// Switches to the next of the `FOG_COLORS`.
+    NextFogColor,
+}
+
+impl Default for LightingSettings {
+    fn default() -> Self {
+        LightingSettings {
+            fog_color: {
+                let (r, g, b) = FOG_COLORS[0];
+                Color::new(r, g, b)
+            },
+            fog_start: 70.0,
+            fog_end: 460.0,
+            lantern_radius: 0.35,
+            lantern_falloff: 0.6,
+            ambient: 0.35,
+            side_shading: 0.75,
+        }
+    }
+}
+
+impl LightingSettings {
+    /// Applies the given change, keeping every setting inside its valid range.
+    pub fn tweak(&mut self, tweak: LightingTweak) {
+        match tweak {
+            LightingTweak::FogDistance(delta) => {
+                self.fog_end = (self.fog_end + delta).max(self.fog_start + 1.0);
+            }
+            LightingTweak::LanternRadius(delta) => {
+                self.lantern_radius = (self.lantern_radius + delta).clamp(0.0, 2.0);
+            }
+            LightingTweak::Ambient(delta) => {
+                self.ambient = (self.ambient + delta).clamp(0.0, 1.0);
+            }
+            LightingTweak::SideShading(delta) => {
+                self.side_shading = (self.side_shading + delta).clamp(0.0, 1.0);
+            }
+            LightingTweak::NextFogColor => {
+                let Color { r, g, b } = self.fog_color;
+                // Colors that aren't in the list start it over.
+                let next = FOG_COLORS
+                    .iter()
+                    .position(|&color| color == (r, g, b))
+                    .map_or(0, |index| (index + 1) % FOG_COLORS.len());
+                let (r, g, b) = FOG_COLORS[next];
+                self.fog_color = Color::new(r, g, b);
+            }
+        }
+    }
+
+    /// How bright a wall face is depending on the side it's looking to.
+    pub fn side_brightness(&self, side: WallSide) -> f32 {
+        match side {
+            WallSide::Vertical => self.side_shading,
+            WallSide::Horizontal => 1.0,
+        }
+    }
+
+    /// How bright a pixel is because of the lantern,
+    /// which is a radial light around the center of the screen.
+    pub fn lantern_brightness(&self, distance_from_center: f32, framebuffer_width: f32) -> f32 {
+        let distance = distance_from_center / (framebuffer_width / 2.0);
+        let light = if self.lantern_falloff <= 0.0 {
+            if distance <= self.lantern_radius {
+                1.0
+            } else {
+                0.0
+            }
+        } else {
+            1.0 - ((distance - self.lantern_radius) / self.lantern_falloff).clamp(0.0, 1.0)
+        };
+
+        self.ambient + (1.0 - self.ambient) * light
+    }
+
+    /// How much of the fog color covers something at the given distance, from `0.0` to `1.0`.
+    pub fn fog_amount(&self, distance: f32) -> f32 {
+        ((distance - self.fog_start) / (self.fog_end - self.fog_start)).clamp(0.0, 1.0)
+    }
+
+    /// Lights a pixel of something that's `distance` world units away from the player
+    /// and `distance_from_center` pixels away from the center of the screen.
+    ///
+    /// `baked_light` is the level of the light map where the pixel is,
+    /// it's added on top of the lantern.
+    pub fn shade(
+        &self,
+        color: &Color,
+        distance: f32,
+        distance_from_center: f32,
+        framebuffer_width: f32,
+        baked_light: f32,
+    ) -> Color {
+        let brightness = (self.lantern_brightness(distance_from_center, framebuffer_width)
+            + baked_light)
+            .min(1.0);
+        let lit = *color * brightness;
+        let fog = self.fog_amount(distance);
+
+        lit * (1.0 - fog) + self.fog_color * fog
+    }
+}
+
+/// Whether light can spread through the cell.
+fn lets_light_through(cell: &BoardCell) -> bool {
+    !matches!(
+        cell,
+        BoardCell::HorizontalWall
+            | BoardCell::VerticalWall
+            | BoardCell::PillarWall
+            | BoardCell::PushWall(_)
+            | BoardCell::Torch
+    )
+}
+
+/// Computes the light level of every cell of the board by flood-filling
+/// the light of each source around the walls.
+///
+/// The light fades linearly with the number of cells it has to go through,
+/// and the light of different sources adds up.
+pub fn bake_light_map(board: &Board) -> Vec<Vec<f32>> {
+    let mut light_map: Vec<Vec<f32>> = board.cells.iter().map(|row| vec![0.0; row.len()]).collect();
+
+    for light in &board.lights {
+        let radius = light.kind.radius();
+        let mut steps: Vec<Vec<Option<usize>>> = board
+            .cells
+            .iter()
+            .map(|row| vec![None; row.len()])
+            .collect();
+        let (i, j) = light.cell;
+        if steps.get(j).and_then(|row| row.get(i)).is_none() {
+            continue;
+        }
+
+        let mut queue = VecDeque::from([(i, j)]);
+        steps[j][i] = Some(0);
+        while let Some((i, j)) = queue.pop_front() {
+            let step = steps[j][i].unwrap();
+            light_map[j][i] +=
+                light.kind.intensity() * (1.0 - step as f32 / radius as f32).max(0.0);
+
+            // The source itself may be a wall, but its light still comes out of it.
+            let spreads = step == 0 || lets_light_through(&board.cells[j][i]);
+            if !spreads || step + 1 >= radius {
+                continue;
+            }
+
+            let neighbours = [
+                (i.wrapping_sub(1), j),
+                (i + 1, j),
+                (i, j.wrapping_sub(1)),
+                (i, j + 1),
+            ];
+            for (ni, nj) in neighbours {
+                if let Some(next @ None) = steps.get_mut(nj).and_then(|row| row.get_mut(ni)) {
+                    *next = Some(step + 1);
+                    queue.push_back((ni, nj));
+                }
+            }
+        }
+    }
+
+    light_map
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_far_away_things_become_fog() {
+        let settings = LightingSettings::default();
+        let color = Color::new(200, 150, 100);
+
+        let Color { r, g, b } = settings.shade(&color, settings.fog_end + 10.0, 0.0, 100.0, 0.0);
+
+        assert_eq!((r, g, b), (0, 2, 12));
+    }
+
+    #[test]
+    fn test_lantern_center_is_fully_lit() {
+        let settings = LightingSettings::default();
+        let color = Color::new(200, 150, 100);
+
+        let Color { r, g, b } = settings.shade(&color, 0.0, 0.0, 100.0, 0.0);
+
+        assert_eq!((r, g, b), (200, 150, 100));
+    }
+
+    #[test]
+    fn test_fog_colors_go_around() {
+        let mut settings = LightingSettings::default();
+
+        let colors: Vec<_> = (0..=FOG_COLORS.len())
+            .map(|_| {
+                settings.tweak(LightingTweak::NextFogColor);
+                let Color { r, g, b } = settings.fog_color;
+                (r, g, b)
+            })
+            .collect();
+
+        assert_eq!(colors[0], FOG_COLORS[1]);
+        assert_eq!(colors[FOG_COLORS.len() - 1], FOG_COLORS[0]);
+        assert_eq!(colors[FOG_COLORS.len()], FOG_COLORS[1]);
+    }
+
+    #[test]
+    fn test_outside_the_lantern_only_ambient_light_remains() {
+        let settings = LightingSettings::default();
+
+        let brightness = settings.lantern_brightness(100.0, 100.0);
+
+        assert_eq!(brightness, settings.ambient);
+    }
+
+    fn board_with_light(rows: &[&str]) -> Board {
+        let mut lights = vec![];
+        let cells = rows
+            .iter()
+            .enumerate()
+            .map(|(j, row)| {
+                row.chars()
+                    .enumerate()
+                    .map(|(i, c)| match c {
+                        '+' => BoardCell::PillarWall,
+                        't' => {
+                            lights.push(LightSource {
+                                cell: (i, j),
+                                kind: LightKind::Torch,
+                            });
+                            BoardCell::Torch
+                        }
+                        _ => BoardCell::Empty,
+                    })
+                    .collect()
+            })
+            .collect();
+
+        let mut board = Board::new(cells, (10.0, 10.0));
+        board.lights = lights;
+        board
+    }
+
+    #[test]
+    fn test_light_fades_away_from_the_source() {
+        let board = board_with_light(&["+t++++++++", "+        +", "++++++++++"]);
+
+        let light_map = bake_light_map(&board);
+
+        assert!(light_map[1][1] > light_map[1][3]);
+        assert!(light_map[1][3] > 0.0);
+        assert_eq!(light_map[1][8], 0.0);
+    }
+
+    #[test]
+    fn test_walls_block_the_light() {
+        let board = board_with_light(&["+t+++", "+ + +", "+++++"]);
+
+        let light_map = bake_light_map(&board);
+
+        assert!(light_map[1][1] > 0.0);
+        assert_eq!(light_map[1][3], 0.0);
+    }
+}
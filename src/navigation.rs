@@ -0,0 +1,354 @@
+//! Finding the way around a `Board`.
+//!
+//! Cells are `(i, j)` pairs, the column and the row inside `Board::cells`.
+//! Walls block the way and doors can be gone through depending on the `Doors` rule,
+//! everything moves between the four neighbours of a cell.
+
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap, VecDeque},
+};
+
+use glm::Vec2;
+
+use crate::{game::is_border, raycaster::GridTraversal, Board, BoardCell};
+
+/// The column and row of a cell of the board.
+pub type Cell = (usize, usize);
+
+/// How doors are treated when looking for a way.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Doors {
+    /// Only doors that are already open can be gone through.
+    OnlyOpen,
+    /// Closed doors can be gone through too, because they can be opened.
+    CanOpen,
+}
+
+/// The cell of the board a point is in.
+pub fn cell_of(board: &Board, point: &Vec2) -> Cell {
+    let (block_width, block_height) = board.cell_dimensions;
+    (
+        (point.x / block_width).max(0.0) as usize,
+        (point.y / block_height).max(0.0) as usize,
+    )
+}
+
+/// The point in the middle of a cell.
+pub fn cell_center(board: &Board, (i, j): Cell) -> Vec2 {
+    let (block_width, block_height) = board.cell_dimensions;
+    Vec2::new(
+        (i as f32 + 0.5) * block_width,
+        (j as f32 + 0.5) * block_height,
+    )
+}
+
+/// Whether nothing opaque stands between the two points.
+/// See-through walls and open doors don't block the sight.
+pub fn line_of_sight(board: &Board, from: &Vec2, to: &Vec2) -> bool {
+    let distance = nalgebra_glm::distance(from, to);
+    let orientation = (to.y - from.y).atan2(to.x - from.x);
+
+    GridTraversal::new(board, from, orientation)
+        .take_while(|step| step.distance < distance)
+        .all(|step| {
+            let (i, j) = step.cell;
+            match &board.cells[j][i] {
+                BoardCell::Door(door) => door.is_passable(),
+                BoardCell::PushWall(_) => false,
+                cell => cell.is_see_through() || !is_border(cell),
+            }
+        })
+}
+
+/// Answers questions about the ways around a board.
+pub struct Navigator<'a> {
+    board: &'a Board,
+    doors: Doors,
+}
+
+impl<'a> Navigator<'a> {
+    pub fn new(board: &'a Board, doors: Doors) -> Self {
+        Navigator { board, doors }
+    }
+
+    /// Whether the cell can be walked into.
+    pub fn is_walkable(&self, (i, j): Cell) -> bool {
+        if self.board.is_reserved((i, j)) {
+            // A push-wall is about to land there.
+            return false;
+        }
+        match self.board.cells.get(j).and_then(|row| row.get(i)) {
+            Some(BoardCell::Door(door)) => self.doors == Doors::CanOpen || door.is_passable(),
+            Some(BoardCell::PushWall(_)) | None => false,
+            Some(cell) => !is_border(cell),
+        }
+    }
+
+    /// The cells next to `cell` that can be walked into.
+    pub fn neighbours(&self, (i, j): Cell) -> impl Iterator<Item = Cell> + '_ {
+        [
+            (i.wrapping_sub(1), j),
+            (i + 1, j),
+            (i, j.wrapping_sub(1)),
+            (i, j + 1),
+        ]
+        .into_iter()
+        .filter(|&cell| self.is_walkable(cell))
+    }
+
+    /// A walkable cell with only one way in.
+    pub fn is_dead_end(&self, cell: Cell) -> bool {
+        self.is_walkable(cell) && self.neighbours(cell).count() == 1
+    }
+
+    /// Every cell that can be reached from `start` in at most `max_steps` steps.
+    pub fn reachable(&self, start: Cell, max_steps: usize) -> Reachable {
+        let mut reached = HashMap::from([(start, (0, None))]);
+        let mut queue = VecDeque::from([(start, 0)]);
+        while let Some((cell, steps)) = queue.pop_front() {
+            if steps >= max_steps {
+                continue;
+            }
+            for next in self.neighbours(cell) {
+                reached.entry(next).or_insert_with(|| {
+                    queue.push_back((next, steps + 1));
+                    (steps + 1, Some(cell))
+                });
+            }
+        }
+
+        Reachable { reached }
+    }
+
+    /// The shortest path from `from` to `to` using a breadth-first search,
+    /// see `Reachable::path_to`.
+    pub fn bfs(&self, from: Cell, to: Cell) -> Option<Vec<Cell>> {
+        self.reachable(from, usize::MAX).path_to(to)
+    }
+
+    /// The shortest path from `from` to `to` using A*, see `Reachable::path_to`.
+    ///
+    /// It visits fewer cells than `bfs` when the target is known.
+    pub fn a_star(&self, from: Cell, to: Cell) -> Option<Vec<Cell>> {
+        let heuristic = |(i, j): Cell| i.abs_diff(to.0) + j.abs_diff(to.1);
+
+        let mut came_from: HashMap<Cell, Cell> = HashMap::new();
+        let mut steps = HashMap::from([(from, 0)]);
+        let mut open = BinaryHeap::from([Reverse((heuristic(from), 0, from))]);
+        while let Some(Reverse((_, cell_steps, cell))) = open.pop() {
+            if cell == to {
+                let mut path = vec![cell];
+                let mut cell = cell;
+                while let Some(&previous) = came_from.get(&cell) {
+                    path.push(previous);
+                    cell = previous;
+                }
+                path.pop();
+                path.reverse();
+                return Some(path);
+            }
+            if cell_steps > steps[&cell] {
+                // A shorter way to this cell was already found.
+                continue;
+            }
+
+            for next in self.neighbours(cell) {
+                let next_steps = cell_steps + 1;
+                if steps.get(&next).is_none_or(|&known| next_steps < known) {
+                    steps.insert(next, next_steps);
+                    came_from.insert(next, cell);
+                    open.push(Reverse((next_steps + heuristic(next), next_steps, next)));
+                }
+            }
+        }
+
+        None
+    }
+
+    /// The steps from every cell of the board to `target`,
+    /// so many walkers can go to the same place without searching a path each.
+    pub fn flow_field(&self, target: Cell) -> FlowField {
+        let mut steps: Vec<Vec<Option<usize>>> = self
+            .board
+            .cells
+            .iter()
+            .map(|row| vec![None; row.len()])
+            .collect();
+
+        if self.is_walkable(target) {
+            steps[target.1][target.0] = Some(0);
+            let mut queue = VecDeque::from([target]);
+            while let Some(cell) = queue.pop_front() {
+                let cell_steps = steps[cell.1][cell.0].unwrap_or_default();
+                for (i, j) in self.neighbours(cell) {
+                    if steps[j][i].is_none() {
+                        steps[j][i] = Some(cell_steps + 1);
+                        queue.push_back((i, j));
+                    }
+                }
+            }
+        }
+
+        FlowField { steps }
+    }
+}
+
+/// The cells reached by a breadth-first search,
+/// with the steps to each one and the cell it was reached from.
+pub struct Reachable {
+    reached: HashMap<Cell, (usize, Option<Cell>)>,
+}
+
+impl Reachable {
+    /// The steps from the start to `cell`, `None` if it wasn't reached.
+    pub fn steps(&self, cell: Cell) -> Option<usize> {
+        self.reached.get(&cell).map(|(steps, _)| *steps)
+    }
+
+    /// The cells to go through to get to `target`, without the start.
+    pub fn path_to(&self, target: Cell) -> Option<Vec<Cell>> {
+        self.reached.get(&target)?;
+
+        let mut path = vec![];
+        let mut cell = target;
+        while let Some(&(_, Some(previous))) = self.reached.get(&cell) {
+            path.push(cell);
+            cell = previous;
+        }
+        path.reverse();
+
+        Some(path)
+    }
+
+    /// Every reached cell and the steps to it, sorted by cell.
+    pub fn cells(&self) -> Vec<(Cell, usize)> {
+        let mut cells: Vec<_> = self
+            .reached
+            .iter()
+            .map(|(&cell, &(steps, _))| (cell, steps))
+            .collect();
+        cells.sort();
+        cells
+    }
+}
+
+/// The steps from every cell to a single target.
+pub struct FlowField {
+    steps: Vec<Vec<Option<usize>>>,
+}
+
+impl FlowField {
+    /// The steps from `cell` to the target, `None` if there's no way.
+    pub fn steps(&self, (i, j): Cell) -> Option<usize> {
+        self.steps
+            .get(j)
+            .and_then(|row| row.get(i))
+            .copied()
+            .flatten()
+    }
+
+    /// The cell to go to from `cell` to get closer to the target.
+    /// It's `None` at the target itself and where there's no way.
+    pub fn next(&self, (i, j): Cell) -> Option<Cell> {
+        let steps = self.steps((i, j))?;
+        [
+            (i.wrapping_sub(1), j),
+            (i + 1, j),
+            (i, j.wrapping_sub(1)),
+            (i, j + 1),
+        ]
+        .into_iter()
+        .find(|&neighbour| self.steps(neighbour).is_some_and(|next| next + 1 == steps))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::board;
+
+    const MAZE: [&str; 5] = ["+++++++", "+     +", "+ +++ +", "+   + +", "+++++++"];
+
+    #[test]
+    fn test_bfs_and_a_star_find_paths_of_the_same_length() {
+        let board = board(&MAZE);
+        let navigator = Navigator::new(&board, Doors::OnlyOpen);
+
+        let bfs = navigator.bfs((1, 3), (5, 3)).unwrap();
+        let a_star = navigator.a_star((1, 3), (5, 3)).unwrap();
+
+        assert_eq!(bfs.len(), 8);
+        assert_eq!(a_star.len(), bfs.len());
+        assert_eq!(a_star.last(), Some(&(5, 3)));
+        for path in [bfs, a_star] {
+            assert!(path.iter().all(|&cell| navigator.is_walkable(cell)));
+        }
+    }
+
+    #[test]
+    fn test_there_is_no_path_into_a_closed_room() {
+        let board = board(&["+++++", "+ + +", "+++++"]);
+        let navigator = Navigator::new(&board, Doors::CanOpen);
+
+        assert_eq!(navigator.bfs((1, 1), (3, 1)), None);
+        assert_eq!(navigator.a_star((1, 1), (3, 1)), None);
+        assert_eq!(navigator.bfs((1, 1), (1, 1)), Some(vec![]));
+    }
+
+    #[test]
+    fn test_closed_doors_only_open_the_way_to_who_can_open_them() {
+        let board = board(&["+++++", "+ d +", "+++++"]);
+
+        let walker = Navigator::new(&board, Doors::OnlyOpen);
+        let opener = Navigator::new(&board, Doors::CanOpen);
+
+        assert_eq!(walker.a_star((1, 1), (3, 1)), None);
+        assert_eq!(opener.a_star((1, 1), (3, 1)), Some(vec![(2, 1), (3, 1)]));
+    }
+
+    #[test]
+    fn test_flow_fields_lead_to_the_target() {
+        let board = board(&MAZE);
+        let navigator = Navigator::new(&board, Doors::OnlyOpen);
+        let field = navigator.flow_field((5, 3));
+
+        let mut cell = (1, 3);
+        let mut steps = 0;
+        while let Some(next) = field.next(cell) {
+            cell = next;
+            steps += 1;
+        }
+
+        assert_eq!(cell, (5, 3));
+        assert_eq!(Some(steps), navigator.flow_field((1, 3)).steps((5, 3)));
+        assert_eq!(field.steps((0, 0)), None);
+    }
+
+    #[test]
+    fn test_walls_block_the_sight_but_fences_dont() {
+        let board = board(&["+++++++", "+ f + +", "+++++++"]);
+
+        assert!(line_of_sight(
+            &board,
+            &Vec2::new(15.0, 15.0),
+            &Vec2::new(35.0, 15.0)
+        ));
+        assert!(!line_of_sight(
+            &board,
+            &Vec2::new(15.0, 15.0),
+            &Vec2::new(55.0, 15.0)
+        ));
+    }
+
+    #[test]
+    fn test_dead_ends_have_a_single_way_in() {
+        let board = board(&MAZE);
+        let navigator = Navigator::new(&board, Doors::OnlyOpen);
+
+        assert!(navigator.is_dead_end((3, 3)));
+        assert!(navigator.is_dead_end((5, 3)));
+        assert!(!navigator.is_dead_end((1, 1)));
+        assert!(!navigator.is_dead_end((0, 0)));
+    }
+}
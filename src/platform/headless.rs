@@ -0,0 +1,101 @@
+use std::collections::VecDeque;
+
+use super::{Key, Platform};
+use crate::framebuffer::Framebuffer;
+
+/// The input of a single frame of a `HeadlessPlatform`.
+#[derive(Debug, Clone, Default)]
+pub struct ScriptedFrame {
+    /// The keys held down during the frame.
+    pub keys: Vec<Key>,
+    /// How much the mouse moves to the right and down during the frame.
+    pub mouse_delta: (i32, i32),
+}
+
+impl ScriptedFrame {
+    pub fn new(keys: &[Key]) -> Self {
+        ScriptedFrame {
+            keys: keys.to_vec(),
+            mouse_delta: (0, 0),
+        }
+    }
+}
+
+/// Plays the game without a display, following a script of inputs.
+///
+/// Every presented frame is copied into `framebuffer`,
+/// and the game ends once every scripted frame has been played.
+pub struct HeadlessPlatform {
+    pub framebuffer: Framebuffer,
+    /// How many frames have been presented.
+    pub frame_count: usize,
+    script: VecDeque<ScriptedFrame>,
+    mouse: (i32, i32),
+}
+
+impl HeadlessPlatform {
+    pub fn new(width: usize, height: usize, script: Vec<ScriptedFrame>) -> Self {
+        let mut platform = HeadlessPlatform {
+            framebuffer: Framebuffer::new(width, height),
+            frame_count: 0,
+            script: script.into(),
+            mouse: (width as i32 / 2, height as i32 / 2),
+        };
+        platform.move_mouse_by_script();
+        platform
+    }
+
+    fn current_frame(&self) -> Option<&ScriptedFrame> {
+        self.script.front()
+    }
+
+    fn move_mouse_by_script(&mut self) {
+        if let Some(frame) = self.script.front() {
+            self.mouse.0 += frame.mouse_delta.0;
+            self.mouse.1 += frame.mouse_delta.1;
+        }
+    }
+}
+
+impl Platform for HeadlessPlatform {
+    fn is_open(&self) -> bool {
+        !self.script.is_empty()
+    }
+
+    fn is_key_down(&self, key: Key) -> bool {
+        self.current_frame()
+            .is_some_and(|frame| frame.keys.contains(&key))
+    }
+
+    fn get_keys_pressed(&mut self) -> Vec<Key> {
+        self.current_frame()
+            .map(|frame| frame.keys.clone())
+            .unwrap_or_default()
+    }
+
+    fn get_mouse_position(&self) -> Option<(i32, i32)> {
+        Some(self.mouse)
+    }
+
+    fn move_mouse_to(&mut self, x: i32, y: i32) {
+        self.mouse = (x, y);
+    }
+
+    fn get_position(&self) -> (isize, isize) {
+        (0, 0)
+    }
+
+    fn get_size(&self) -> (usize, usize) {
+        (self.framebuffer.width, self.framebuffer.height)
+    }
+
+    fn set_cursor_visibility(&mut self, _visible: bool) {}
+
+    fn present(&mut self, framebuffer: &Framebuffer) {
+        self.framebuffer.buffer.clone_from(&framebuffer.buffer);
+        self.frame_count += 1;
+
+        self.script.pop_front();
+        self.move_mouse_by_script();
+    }
+}
@@ -0,0 +1,59 @@
+use crate::framebuffer::Framebuffer;
+
+mod headless;
+#[cfg(feature = "window")]
+mod window;
+pub use headless::*;
+#[cfg(feature = "window")]
+pub use window::*;
+
+/// The keys the game listens to, every platform maps its own keys to these.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Key {
+    W,
+    A,
+    S,
+    D,
+    C,
+    E,
+    P,
+    R,
+    Space,
+    Escape,
+    Key1,
+    Key2,
+    Key3,
+    Key4,
+    Key5,
+    Key6,
+    Key7,
+    Key8,
+    Key9,
+}
+
+/// Everything the game needs from the machine it runs on:
+/// somewhere to show the frames, the keyboard and the mouse.
+pub trait Platform {
+    /// Whether the game should keep running.
+    fn is_open(&self) -> bool;
+
+    fn is_key_down(&self, key: Key) -> bool;
+
+    /// The keys pressed since the last frame, including the ones being held down.
+    fn get_keys_pressed(&mut self) -> Vec<Key>;
+
+    /// The position of the mouse on the screen.
+    fn get_mouse_position(&self) -> Option<(i32, i32)>;
+
+    fn move_mouse_to(&mut self, x: i32, y: i32);
+
+    /// The position of the window on the screen.
+    fn get_position(&self) -> (isize, isize);
+
+    fn get_size(&self) -> (usize, usize);
+
+    fn set_cursor_visibility(&mut self, visible: bool);
+
+    /// Shows the finished frame, which ends it.
+    fn present(&mut self, framebuffer: &Framebuffer);
+}
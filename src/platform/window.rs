@@ -0,0 +1,159 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use minifb::{KeyRepeat, Window, WindowOptions};
+use mouse_rs::{types::Point, Mouse};
+
+use super::{Key, Platform};
+use crate::framebuffer::{Framebuffer, ScaleFilter};
+
+/// Plays the game on a desktop window.
+pub struct WindowPlatform {
+    window: Window,
+    mouse: Mouse,
+    width: usize,
+    height: usize,
+    /// How frames rendered at a different size than the window are scaled to it.
+    filter: ScaleFilter,
+    scaled_buffer: Vec<u32>,
+    frame_delay: Duration,
+    frame_start: Instant,
+    last_recorded_frames: VecDeque<u128>,
+}
+
+impl WindowPlatform {
+    const LAST_RECORDED_FRAMES_MAX_COUNT: usize = 60;
+
+    pub fn new(width: usize, height: usize, target_framerate: u64, filter: ScaleFilter) -> Self {
+        let window_options = WindowOptions {
+            resize: true,
+            scale: minifb::Scale::FitScreen,
+            ..WindowOptions::default()
+        };
+
+        let mut window = Window::new("Hornystein", width, height, window_options).unwrap();
+        window.set_key_repeat_delay(0.01);
+        window.set_cursor_visibility(true);
+
+        WindowPlatform {
+            window,
+            mouse: Mouse::new(),
+            width,
+            height,
+            filter,
+            scaled_buffer: vec![0; width * height],
+            frame_delay: Duration::from_millis(1000 / target_framerate),
+            frame_start: Instant::now(),
+            last_recorded_frames: VecDeque::with_capacity(
+                WindowPlatform::LAST_RECORDED_FRAMES_MAX_COUNT,
+            ),
+        }
+    }
+}
+
+impl Platform for WindowPlatform {
+    fn is_open(&self) -> bool {
+        self.window.is_open()
+    }
+
+    fn is_key_down(&self, key: Key) -> bool {
+        self.window
+            .get_keys()
+            .into_iter()
+            .any(|down| to_key(down) == Some(key))
+    }
+
+    fn get_keys_pressed(&mut self) -> Vec<Key> {
+        self.window
+            .get_keys_pressed(KeyRepeat::Yes)
+            .into_iter()
+            .filter_map(to_key)
+            .collect()
+    }
+
+    fn get_mouse_position(&self) -> Option<(i32, i32)> {
+        self.mouse.get_position().ok().map(|Point { x, y }| (x, y))
+    }
+
+    fn move_mouse_to(&mut self, x: i32, y: i32) {
+        self.mouse.move_to(x, y).expect("Unable to move mouse!");
+    }
+
+    fn get_position(&self) -> (isize, isize) {
+        self.window.get_position()
+    }
+
+    fn get_size(&self) -> (usize, usize) {
+        self.window.get_size()
+    }
+
+    fn set_cursor_visibility(&mut self, visible: bool) {
+        self.window.set_cursor_visibility(visible);
+    }
+
+    fn present(&mut self, framebuffer: &Framebuffer) {
+        // Update the window with the framebuffer contents
+        let buffer = if (framebuffer.width, framebuffer.height) == (self.width, self.height) {
+            &framebuffer.buffer
+        } else {
+            framebuffer.scale_into(
+                &mut self.scaled_buffer,
+                self.width,
+                self.height,
+                self.filter,
+            );
+            &self.scaled_buffer
+        };
+        self.window
+            .update_with_buffer(buffer, self.width, self.height)
+            .expect("Couldn't update the framebuffer!");
+
+        let end = Instant::now();
+        if self.last_recorded_frames.len() == WindowPlatform::LAST_RECORDED_FRAMES_MAX_COUNT {
+            self.last_recorded_frames.pop_front();
+        }
+        self.last_recorded_frames
+            .push_back((end - self.frame_start).as_millis());
+
+        let avg_millis: f32 = self
+            .last_recorded_frames
+            .iter()
+            .map(|&u| u as f32)
+            .sum::<f32>()
+            / WindowPlatform::LAST_RECORDED_FRAMES_MAX_COUNT as f32;
+        let avg_frames = 1000.0 / avg_millis;
+        self.window
+            .set_title(format!("Hornystein - {:.2} fps", avg_frames).as_ref());
+        std::thread::sleep(self.frame_delay);
+
+        self.frame_start = Instant::now();
+    }
+}
+
+/// The game's key for a key of the window, if the game cares about it.
+fn to_key(key: minifb::Key) -> Option<Key> {
+    Some(match key {
+        minifb::Key::W => Key::W,
+        minifb::Key::A => Key::A,
+        minifb::Key::S => Key::S,
+        minifb::Key::D => Key::D,
+        minifb::Key::C => Key::C,
+        minifb::Key::E => Key::E,
+        minifb::Key::P => Key::P,
+        minifb::Key::R => Key::R,
+        minifb::Key::Space => Key::Space,
+        minifb::Key::Escape => Key::Escape,
+        minifb::Key::Key1 => Key::Key1,
+        minifb::Key::Key2 => Key::Key2,
+        minifb::Key::Key3 => Key::Key3,
+        minifb::Key::Key4 => Key::Key4,
+        minifb::Key::Key5 => Key::Key5,
+        minifb::Key::Key6 => Key::Key6,
+        minifb::Key::Key7 => Key::Key7,
+        minifb::Key::Key8 => Key::Key8,
+        minifb::Key::Key9 => Key::Key9,
+        _ => return None,
+    })
+}
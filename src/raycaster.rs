@@ -1,85 +1,439 @@
-use nalgebra_glm::vec2_to_vec3;
+use nalgebra_glm::{vec2_to_vec3, Vec2};
 
-use crate::{framebuffer::Framebuffer, render::scale_to_fit, Board, BoardCell, Model, Player};
+use crate::{framebuffer::Framebuffer, render::scale_to_fit, Board, BoardCell, Player};
+
+/// The face of a cell that was hit by a ray.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WallSide {
+    /// The ray crossed a vertical grid line, so it hit an east or west face.
+    Vertical,
+    /// The ray crossed a horizontal grid line, so it hit a north or south face.
+    Horizontal,
+}
 
 pub struct Intersect {
+    /// The euclidean distance from the ray origin to the hit point.
     pub distance: f32,
     pub impact: BoardCell,
+    /// Horizontal texture coordinate of the hit on the wall face, in the range `[0, 1)`.
     pub bx: f32,
+    /// The exact point where the ray hit the wall.
+    pub point: Vec2,
+    pub side: WallSide,
 }
 
-pub fn cast_ray_3d(framebuffer: &mut Framebuffer, data: &Model, orientation: f32) -> Intersect {
-    let mut distance = 0.0;
+/// A single cell visited by a `GridTraversal`.
+pub struct GridStep {
+    /// Column and row of the cell inside `Board::cells`.
+    pub cell: (usize, usize),
+    /// Distance along the ray at which the cell was entered.
+    pub distance: f32,
+    /// The grid line that was crossed to enter the cell.
+    pub side: WallSide,
+}
 
-    framebuffer.set_current_color(0x000000);
-    let Model { board, player, .. } = data;
-    loop {
-        let cos = distance * orientation.cos();
-        let sin = distance * orientation.sin();
+/// Walks every cell of a `Board` crossed by a ray, in order, using a
+/// digital differential analyzer (DDA).
+///
+/// Unlike marching in fixed steps this never skips a cell, no matter how thin
+/// the corner the ray goes through is. The iterator ends when the ray leaves the board.
+pub struct GridTraversal<'a> {
+    board: &'a Board,
+    cell: (isize, isize),
+    step: (isize, isize),
+    side_distance: Vec2,
+    delta_distance: Vec2,
+}
 
-        let x = player.position.x + cos;
-        let y = player.position.y + sin;
+impl<'a> GridTraversal<'a> {
+    pub fn new(board: &'a Board, origin: &Vec2, orientation: f32) -> Self {
+        let (block_width, block_height) = board.cell_dimensions;
+        let direction = Vec2::new(orientation.cos(), orientation.sin());
 
-        // println!("Checking cords at: {}, {}", x, y);
+        let cell = (
+            (origin.x / block_width).floor() as isize,
+            (origin.y / block_height).floor() as isize,
+        );
 
-        let i = (x / board.cell_dimensions.0) as usize;
-        let j = (y / board.cell_dimensions.1) as usize;
-        let cell = &board.cells[j][i];
+        let (step_x, side_x) = if direction.x < 0.0 {
+            (-1, (origin.x - cell.0 as f32 * block_width) / -direction.x)
+        } else {
+            (
+                1,
+                ((cell.0 + 1) as f32 * block_width - origin.x) / direction.x,
+            )
+        };
+        let (step_y, side_y) = if direction.y < 0.0 {
+            (-1, (origin.y - cell.1 as f32 * block_height) / -direction.y)
+        } else {
+            (
+                1,
+                ((cell.1 + 1) as f32 * block_height - origin.y) / direction.y,
+            )
+        };
 
-        let (block_width, block_height) = board.cell_dimensions;
+        // A direction component of 0 yields infinity, which means that axis is never crossed.
+        let delta_distance = Vec2::new(
+            (block_width / direction.x).abs(),
+            (block_height / direction.y).abs(),
+        );
 
-        let hitx = x - i as f32 * block_width;
-        let hity = y - j as f32 * block_height;
+        GridTraversal {
+            board,
+            cell,
+            step: (step_x, step_y),
+            side_distance: Vec2::new(side_x, side_y),
+            delta_distance,
+        }
+    }
+}
 
-        let bx = if 1.0 < hitx && hitx < (block_width - 1.0) {
-            hitx / block_width
+impl<'a> Iterator for GridTraversal<'a> {
+    type Item = GridStep;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (distance, side) = if self.side_distance.x < self.side_distance.y {
+            let distance = self.side_distance.x;
+            self.side_distance.x += self.delta_distance.x;
+            self.cell.0 += self.step.0;
+            (distance, WallSide::Vertical)
         } else {
-            // println!("Had to use hity. hitx {}, blockwidth {}", hitx, block_width);
-            hity / block_height
+            let distance = self.side_distance.y;
+            self.side_distance.y += self.delta_distance.y;
+            self.cell.1 += self.step.1;
+            (distance, WallSide::Horizontal)
         };
 
-        // println!("Checking cell [{}] at: {}, {}", cell, x, y);
-        match cell {
-            BoardCell::HorizontalWall | BoardCell::VerticalWall | BoardCell::PillarWall => {
-                return Intersect {
-                    distance,
-                    impact: cell.clone(),
-                    bx,
+        if !distance.is_finite() || self.cell.0 < 0 || self.cell.1 < 0 {
+            return None;
+        }
+
+        let (i, j) = (self.cell.0 as usize, self.cell.1 as usize);
+        self.board
+            .cells
+            .get(j)
+            .and_then(|row| row.get(i))
+            .map(|_| GridStep {
+                cell: (i, j),
+                distance,
+                side,
+            })
+    }
+}
+
+/// Casts a ray from the player until it hits an opaque wall.
+///
+/// Returns `None` if the ray leaves the board without hitting anything.
+pub fn cast_ray_3d(board: &Board, player: &Player, orientation: f32) -> Option<Intersect> {
+    let direction = Vec2::new(orientation.cos(), orientation.sin());
+
+    GridTraversal::new(board, &player.position, orientation)
+        .filter_map(|step| hit_cell(board, &player.position, &direction, &step))
+        .find(|intersect| !intersect.impact.is_see_through())
+}
+
+/// Casts a ray from the player collecting every wall it hits, from near to far,
+/// until it hits an opaque one.
+///
+/// The last layer is only see-through if the ray left the board without hitting an opaque wall.
+pub fn cast_ray_layers(board: &Board, player: &Player, orientation: f32) -> Vec<Intersect> {
+    let direction = Vec2::new(orientation.cos(), orientation.sin());
+
+    let mut layers = vec![];
+    let hits = GridTraversal::new(board, &player.position, orientation)
+        .filter_map(|step| hit_cell(board, &player.position, &direction, &step));
+    for intersect in hits {
+        let is_opaque = !intersect.impact.is_see_through();
+        layers.push(intersect);
+
+        if is_opaque {
+            break;
+        }
+    }
+
+    layers
+}
+
+/// Checks if a ray going from `origin` towards `direction` hits something inside the cell of `step`.
+fn hit_cell(board: &Board, origin: &Vec2, direction: &Vec2, step: &GridStep) -> Option<Intersect> {
+    let (block_width, block_height) = board.cell_dimensions;
+    let (i, j) = step.cell;
+    let cell = &board.cells[j][i];
+
+    // Push-walls can be halfway into this cell while they slide.
+    let push_wall_hit = board
+        .push_walls_around(i, j)
+        .filter_map(|(wall, min, max)| {
+            let (distance, side) = intersect_block(origin, direction, &min, &max)?;
+            let point = origin + direction * distance;
+
+            // Hits outside of this cell will be found while visiting their own cell.
+            let epsilon = 1e-3;
+            let cell_min = Vec2::new(i as f32 * block_width, j as f32 * block_height);
+            let cell_max = cell_min + Vec2::new(block_width, block_height);
+            let inside_cell = (cell_min.x - epsilon..=cell_max.x + epsilon).contains(&point.x)
+                && (cell_min.y - epsilon..=cell_max.y + epsilon).contains(&point.y);
+            if !inside_cell {
+                return None;
+            }
+
+            let bx = match side {
+                WallSide::Vertical => (point.y - min.y) / block_height,
+                WallSide::Horizontal => (point.x - min.x) / block_width,
+            };
+
+            Some(Intersect {
+                distance,
+                impact: BoardCell::PushWall(wall.clone()),
+                bx: bx.clamp(0.0, 1.0 - f32::EPSILON),
+                point,
+                side,
+            })
+        })
+        .min_by(|a, b| a.distance.total_cmp(&b.distance));
+    if push_wall_hit.is_some() {
+        return push_wall_hit;
+    }
+
+    match cell {
+        BoardCell::HorizontalWall
+        | BoardCell::VerticalWall
+        | BoardCell::PillarWall
+        | BoardCell::Fence
+        | BoardCell::Bars
+        | BoardCell::Torch
+        | BoardCell::Goal(_) => {
+            let point = origin + direction * step.distance;
+
+            // Flip the coordinate on the faces looking towards the origin
+            // so textures aren't mirrored depending on the side they're seen from.
+            let bx = match step.side {
+                WallSide::Vertical => {
+                    let bx = (point.y - j as f32 * block_height) / block_height;
+                    if direction.x < 0.0 {
+                        1.0 - bx
+                    } else {
+                        bx
+                    }
+                }
+                WallSide::Horizontal => {
+                    let bx = (point.x - i as f32 * block_width) / block_width;
+                    if direction.y > 0.0 {
+                        1.0 - bx
+                    } else {
+                        bx
+                    }
                 }
+            };
+
+            Some(Intersect {
+                distance: step.distance,
+                impact: cell.clone(),
+                bx: bx.clamp(0.0, 1.0 - f32::EPSILON),
+                point,
+                side: step.side,
+            })
+        }
+        BoardCell::Door(door) => {
+            // The door is recessed into the middle of its cell, so the ray
+            // only hits it if it reaches that plane before leaving the cell.
+            let (distance, bx) = match door.side {
+                WallSide::Horizontal => {
+                    let middle = (j as f32 + 0.5) * block_height;
+                    let distance = (middle - origin.y) / direction.y;
+                    let x = origin.x + direction.x * distance;
+                    (distance, (x - i as f32 * block_width) / block_width)
+                }
+                WallSide::Vertical => {
+                    let middle = (i as f32 + 0.5) * block_width;
+                    let distance = (middle - origin.x) / direction.x;
+                    let y = origin.y + direction.y * distance;
+                    (distance, (y - j as f32 * block_height) / block_height)
+                }
+            };
+
+            let inside_cell = (0.0..1.0).contains(&bx);
+            // While opening the door slides sideways leaving a gap behind.
+            let behind_gap = bx < door.open_fraction;
+            if !distance.is_finite() || distance < step.distance || !inside_cell || behind_gap {
+                return None;
             }
-            _ => {}
+
+            Some(Intersect {
+                distance,
+                impact: cell.clone(),
+                bx: bx - door.open_fraction,
+                point: origin + direction * distance,
+                side: door.side,
+            })
         }
+        _ => None,
+    }
+}
 
-        distance += 1.0;
+/// Intersects a ray with the axis aligned block going from `min` to `max`.
+/// Returns the distance at which the ray enters the block and the face it enters through.
+fn intersect_block(
+    origin: &Vec2,
+    direction: &Vec2,
+    min: &Vec2,
+    max: &Vec2,
+) -> Option<(f32, WallSide)> {
+    let tx1 = (min.x - origin.x) / direction.x;
+    let tx2 = (max.x - origin.x) / direction.x;
+    let ty1 = (min.y - origin.y) / direction.y;
+    let ty2 = (max.y - origin.y) / direction.y;
+
+    let (enter_x, exit_x) = (tx1.min(tx2), tx1.max(tx2));
+    let (enter_y, exit_y) = (ty1.min(ty2), ty1.max(ty2));
+
+    let enter = enter_x.max(enter_y);
+    let exit = exit_x.min(exit_y);
+    if enter > exit || enter < 0.0 {
+        return None;
     }
+
+    let side = if enter_x > enter_y {
+        WallSide::Vertical
+    } else {
+        WallSide::Horizontal
+    };
+
+    Some((enter, side))
 }
 
 pub fn cast_ray_2d(framebuffer: &mut Framebuffer, maze: &Board, player: &Player, orientation: f32) {
-    let mut d = 0.0;
+    let distance = match cast_ray_3d(maze, player, orientation) {
+        Some(intersect) => intersect.distance,
+        None => return,
+    };
 
     framebuffer.set_current_color(0x000000);
-    loop {
-        let cos = d * orientation.cos();
-        let sin = d * orientation.sin();
+    let direction = Vec2::new(orientation.cos(), orientation.sin());
+    let mut d = 0.0;
+    while d <= distance {
+        let position = player.position + direction * d;
+        let position = scale_to_fit(framebuffer, maze, vec2_to_vec3(&position));
+        let _ = framebuffer.paint_point(position);
 
-        let x = player.position.x + cos;
-        let y = player.position.y + sin;
-        let position = nalgebra_glm::Vec2::new(x, y);
+        d += 1.0;
+    }
+}
 
-        let position = scale_to_fit(framebuffer, vec2_to_vec3(&position));
-        let _ = framebuffer.paint_point(position);
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{board, player_at};
+    use crate::PushWallState;
 
-        let i = (x / maze.cell_dimensions.0) as usize;
-        let j = (y / maze.cell_dimensions.1) as usize;
-        let cell = &maze.cells[j][i];
+    #[test]
+    fn test_hits_vertical_face_at_exact_distance() {
+        let board = board(&["+-+++", "|   |", "+-+++"]);
+        let player = player_at(Vec2::new(15.0, 15.0));
 
-        match cell {
-            BoardCell::HorizontalWall | BoardCell::VerticalWall | BoardCell::PillarWall => {
-                return;
-            }
-            _ => {}
+        let intersect = cast_ray_3d(&board, &player, 0.0).unwrap();
+
+        assert!(intersect.impact == BoardCell::VerticalWall);
+        assert_eq!(intersect.side, WallSide::Vertical);
+        assert!((intersect.distance - 25.0).abs() < 1e-4);
+        assert!((intersect.point.x - 40.0).abs() < 1e-4);
+        assert!((intersect.bx - 0.5).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_hits_horizontal_face() {
+        let board = board(&["+-+++", "|   |", "+-+++"]);
+        let player = player_at(Vec2::new(15.0, 15.0));
+
+        let intersect = cast_ray_3d(&board, &player, -std::f32::consts::FRAC_PI_2).unwrap();
+
+        assert!(intersect.impact == BoardCell::HorizontalWall);
+        assert_eq!(intersect.side, WallSide::Horizontal);
+        assert!((intersect.distance - 5.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_hits_just_past_a_corner() {
+        // The ray grazes the top left corner of the pillar.
+        let board = board(&["     ", "  +  ", "     "]);
+        let player = player_at(Vec2::new(5.0, 5.0));
+
+        let intersect = cast_ray_3d(&board, &player, 0.33).unwrap();
+
+        assert!(intersect.impact == BoardCell::PillarWall);
+        assert_eq!(intersect.side, WallSide::Vertical);
+        assert!((intersect.point.x - 20.0).abs() < 1e-4);
+        assert!(intersect.point.y > 10.0 && intersect.point.y < 10.5);
+    }
+
+    #[test]
+    fn test_doors_are_recessed_into_their_cell() {
+        let board = board(&["+-+++", "| d |", "+-+++"]);
+        let player = player_at(Vec2::new(15.0, 15.0));
+
+        let intersect = cast_ray_3d(&board, &player, 0.0).unwrap();
+
+        assert!(matches!(intersect.impact, BoardCell::Door(_)));
+        assert!((intersect.distance - 10.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_rays_go_through_the_gap_of_an_opening_door() {
+        let mut board = board(&["+-+++", "| d |", "+-+++"]);
+        if let BoardCell::Door(door) = &mut board.cells[1][2] {
+            door.open_fraction = 0.6;
         }
 
-        d += 1.0;
+        // Aims at the lower part of the door, that's still closed.
+        let intersect = cast_ray_3d(&board, &player_at(Vec2::new(15.0, 17.0)), 0.0).unwrap();
+        assert!(matches!(intersect.impact, BoardCell::Door(_)));
+        assert!((intersect.bx - 0.1).abs() < 1e-4);
+
+        // Aims at the gap the door left behind.
+        let intersect = cast_ray_3d(&board, &player_at(Vec2::new(15.0, 13.0)), 0.0).unwrap();
+        assert!(intersect.impact == BoardCell::VerticalWall);
+    }
+
+    #[test]
+    fn test_push_walls_are_hit_at_their_current_offset() {
+        let mut board = board(&["+++++++", "+ s   +", "+++++++"]);
+        if let BoardCell::PushWall(wall) = &mut board.cells[1][2] {
+            wall.state = PushWallState::Moving;
+            wall.direction = (1, 0);
+            wall.offset = 0.5;
+        }
+        let player = player_at(Vec2::new(15.0, 15.0));
+
+        let intersect = cast_ray_3d(&board, &player, 0.0).unwrap();
+
+        assert!(matches!(intersect.impact, BoardCell::PushWall(_)));
+        assert_eq!(intersect.side, WallSide::Vertical);
+        assert!((intersect.distance - 10.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_layers_go_past_see_through_walls() {
+        let board = board(&["+++++++", "+ f f |", "+++++++"]);
+        let player = player_at(Vec2::new(15.0, 15.0));
+
+        let layers = cast_ray_layers(&board, &player, 0.0);
+        let distances: Vec<f32> = layers.iter().map(|layer| layer.distance).collect();
+
+        assert_eq!(layers.len(), 3);
+        assert!(layers[0].impact == BoardCell::Fence);
+        assert!(layers[1].impact == BoardCell::Fence);
+        assert!(layers[2].impact == BoardCell::VerticalWall);
+        assert!(distances.windows(2).all(|d| d[0] < d[1]));
+
+        let intersect = cast_ray_3d(&board, &player, 0.0).unwrap();
+        assert!(intersect.impact == BoardCell::VerticalWall);
+    }
+
+    #[test]
+    fn test_leaving_the_board_returns_none() {
+        let board = board(&["     ", "     ", "     "]);
+        let player = player_at(Vec2::new(5.0, 5.0));
+
+        assert!(cast_ray_3d(&board, &player, 0.3).is_none());
     }
 }
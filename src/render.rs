@@ -1,55 +1,174 @@
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::{ops::Range, thread};
 
 use glm::Vec3;
-use nalgebra_glm::vec2_to_vec3;
+use nalgebra_glm::{vec2_to_vec3, Vec2};
 
 use crate::{
+    animation::{Animation, AnimationClock},
     color::Color,
     framebuffer::Framebuffer,
-    raycaster::{cast_ray_2d, cast_ray_3d},
-    texture::{GameTextures, Texture},
-    BoardCell, GameStatus, Model,
+    lighting::LightingSettings,
+    raycaster::{cast_ray_2d, cast_ray_layers, Intersect},
+    sprite::{rotation_index, sort_far_to_near, HasSprite, Sprite, SpriteTexture},
+    text::{draw_text, format_time, text_width, GLYPH_HEIGHT},
+    texture::{AnimatedTexture, GameTextures, Texture},
+    Board, BoardCell, GameStatus, Model, Player, Surface,
 };
 
-fn from_char_to_texture<'a>(c: &BoardCell, textures: &'a GameTextures) -> Option<&'a Texture> {
+/// How many ticks every frame of the flame of a torch lasts.
+const TORCH_FLICKER_TICKS: u64 = 6;
+
+/// The texture of a wall cell, `animation` picks the frame of animated ones.
+fn from_char_to_texture<'a>(
+    c: &BoardCell,
+    textures: &'a GameTextures,
+    clock: &AnimationClock,
+    animation: &Animation,
+) -> Option<&'a Texture> {
     match c {
         BoardCell::HorizontalWall => Some(&textures.horizontal_wall),
         BoardCell::VerticalWall => Some(&textures.vertical_wall),
         BoardCell::PillarWall => Some(&textures.corner_wall),
+        BoardCell::Door(_) => Some(&textures.door),
+        BoardCell::Fence => Some(&textures.fence),
+        BoardCell::Bars => Some(&textures.bars),
+        BoardCell::Torch => Some(
+            textures
+                .torch
+                .frame(animation.frame(clock, textures.torch.frame_count)),
+        ),
+        BoardCell::Goal(exit) if exit.locked => Some(&textures.exit_locked),
+        BoardCell::Goal(_) => Some(&textures.exit_open),
+        // Push-walls are secret, so they look like any other wall.
+        BoardCell::PushWall(_) => Some(&textures.vertical_wall),
         _ => None,
     }
 }
 
+fn from_sprite_texture<'a>(
+    sprite: &Sprite,
+    viewer: &Vec2,
+    textures: &'a GameTextures,
+    clock: &AnimationClock,
+) -> &'a Texture {
+    match sprite.texture {
+        SpriteTexture::LoliBunny => {
+            let animation = textures.lolibunny.rotation(rotation_index(sprite, viewer));
+            animation.frame(sprite.animation.frame(clock, animation.frame_count))
+        }
+        SpriteTexture::Hunter => &textures.hunter,
+    }
+}
+
+fn from_surface_to_texture<'a>(s: &Surface, textures: &'a GameTextures) -> &'a Texture {
+    match s {
+        Surface::Grass => &textures.grass,
+        Surface::Cobblestone => &textures.cobblestone,
+        Surface::Planks => &textures.planks,
+    }
+}
+
 fn from_cell_to_color(c: &BoardCell) -> Color {
     match c {
-        BoardCell::HorizontalWall | BoardCell::VerticalWall | BoardCell::PillarWall => 0xff00ff,
+        BoardCell::HorizontalWall
+        | BoardCell::VerticalWall
+        | BoardCell::PillarWall
+        | BoardCell::PushWall(_) => 0xff00ff,
+        BoardCell::Torch => 0xffa500,
+        BoardCell::Fence | BoardCell::Bars => 0xaa55aa,
+        BoardCell::Door(_) => 0x8b4513,
+        BoardCell::Goal(exit) if exit.locked => 0x206040,
+        BoardCell::Goal(_) => 0x40ff90,
         _ => 0xffffff,
     }
     .into()
 }
 
-pub fn init_render(framebuffer: &mut Framebuffer, data: &Model) {
-    let (framebuffer_width, framebuffer_height) = data.framebuffer_dimensions;
+/// The highest elevation, in radians, covered by the sky panorama.
+const SKY_MAX_ELEVATION: f32 = std::f32::consts::FRAC_PI_4;
+/// The world angle where the moon rises.
+const MOON_RISE_AZIMUTH: f32 = -std::f32::consts::FRAC_PI_2;
+/// How many radians the moon travels through the sky during the night.
+const MOON_SWEEP: f32 = std::f32::consts::PI;
+/// The elevation of the moon when it rises and sets, in radians.
+const MOON_MIN_ELEVATION: f32 = 0.22;
+/// The elevation of the moon at midnight, in radians.
+const MOON_MAX_ELEVATION: f32 = 0.45;
+const MOON_ANGULAR_RADIUS: f32 = 0.18;
+
+/// The perspective projection shared by walls and sprites.
+pub struct Projection {
+    /// Distance from the eye to the projection plane in pixels.
+    /// It's derived from the player's FOV so the whole framebuffer width fits inside it.
+    pub plane_distance: f32,
+    pub half_width: f32,
+    pub half_height: f32,
+    /// The row of the horizon, it moves away from `half_height` as the player looks up or down.
+    pub horizon: f32,
+    /// The height of a wall in world units.
+    pub wall_height: f32,
+    /// The height of the player's eyes from the floor in world units.
+    pub eye_height: f32,
+}
 
-    // Render sky
-    let half_height = framebuffer_height / 2;
-    let color = 0x00000f;
-    framebuffer.set_current_color(color);
-    for i in 0..(framebuffer_width) {
-        for j in 0..half_height {
-            let _ = framebuffer.paint_point(nalgebra_glm::Vec3::new(i as f32, j as f32, 0.0));
+impl Projection {
+    pub fn new(data: &Model) -> Self {
+        let (framebuffer_width, framebuffer_height) = data.framebuffer_dimensions;
+        let half_width = framebuffer_width as f32 / 2.0;
+        let half_height = framebuffer_height as f32 / 2.0;
+        let plane_distance = half_width / (data.player.fov / 2.0).tan();
+
+        Projection {
+            plane_distance,
+            half_width,
+            half_height,
+            horizon: half_height + data.player.pitch * framebuffer_height as f32,
+            wall_height: data.board.cell_dimensions.1,
+            eye_height: data.player.eye_height,
         }
     }
 
-    // Render ground
-    let color = 0x140d00;
-    framebuffer.set_current_color(color);
-    for i in 0..(framebuffer_width) {
-        for j in half_height..framebuffer_height {
-            let _ = framebuffer.paint_point(nalgebra_glm::Vec3::new(i as f32, j as f32, 0.0));
-        }
+    /// The angle, relative to the player's orientation, of the ray that goes
+    /// through the center of the column `x`.
+    pub fn column_angle(&self, x: usize) -> f32 {
+        ((x as f32 + 0.5 - self.half_width) / self.plane_distance).atan()
+    }
+
+    /// The horizontal screen position of something seen at `relative_angle`
+    /// from the player's orientation.
+    pub fn screen_x(&self, relative_angle: f32) -> f32 {
+        self.half_width + relative_angle.tan() * self.plane_distance
+    }
+
+    /// The perpendicular distance to the floor (or ceiling) seen through the row `y`.
+    pub fn surface_distance(&self, y: usize) -> f32 {
+        let rows_from_horizon = y as f32 + 0.5 - self.horizon;
+        let height = if rows_from_horizon > 0.0 {
+            self.eye_height
+        } else {
+            self.wall_height - self.eye_height
+        };
+        height * self.plane_distance / rows_from_horizon.abs()
+    }
+
+    /// The rows where the top and the bottom of a wall seen at the given perpendicular distance are.
+    pub fn wall_rows(&self, perpendicular_distance: f32) -> (f32, f32) {
+        let scale = self.plane_distance / perpendicular_distance;
+        let top = self.horizon - (self.wall_height - self.eye_height) * scale;
+        let bottom = self.horizon + self.eye_height * scale;
+        (top, bottom)
     }
-    framebuffer.save_as_background();
+
+    /// The height in pixels of a wall seen at the given perpendicular distance.
+    pub fn project_height(&self, perpendicular_distance: f32) -> f32 {
+        self.wall_height * self.plane_distance / perpendicular_distance
+    }
+}
+
+pub fn init_render(framebuffer: &mut Framebuffer, data: &Model) {
+    // The sky and the ground depend on where the player looks,
+    // so they're painted every frame instead of being part of the background.
+    framebuffer.set_background_color(Color::black());
 
     render(framebuffer, data);
 }
@@ -59,57 +178,64 @@ pub fn render(framebuffer: &mut Framebuffer, data: &Model) {
     render3d(framebuffer, data)
 }
 
-pub fn scale_to_fit(framebuffer: &Framebuffer, v: Vec3) -> Vec3 {
+/// Where the minimap goes in the framebuffer, given as its top left corner,
+/// and how many pixels a world unit takes in it.
+///
+/// The minimap keeps the proportions of the maze and fits inside
+/// the bottom right corner of the framebuffer.
+fn minimap_layout(framebuffer: &Framebuffer, board: &Board) -> (Vec2, f32) {
     let f_width = framebuffer.width as f32;
     let f_height = framebuffer.height as f32;
 
-    let width = f_width * 0.2;
-    let height = f_height * 0.2;
+    let (block_width, block_height) = board.cell_dimensions;
+    let world_width = board.cells.first().map_or(0, |row| row.len()) as f32 * block_width;
+    let world_height = board.cells.len() as f32 * block_height;
 
-    let padding = 20.0;
-    let start_x = f_width - width - padding;
-    let start_y = f_height - height - padding;
+    let max_width = f_width * 0.2;
+    let max_height = f_height * 0.2;
+    let scale = (max_width / world_width).min(max_height / world_height);
 
-    let x = v.x / f_width * width + start_x;
-    let y = v.y / f_height * height + start_y;
+    let padding = f_width / 54.0;
+    let start_x = f_width - world_width * scale - padding;
+    let start_y = f_height - world_height * scale - padding;
+
+    (Vec2::new(start_x, start_y), scale)
+}
+
+/// Maps a point of the world to the minimap in the bottom right corner of the framebuffer.
+pub fn scale_to_fit(framebuffer: &Framebuffer, board: &Board, v: Vec3) -> Vec3 {
+    let (start, scale) = minimap_layout(framebuffer, board);
+
+    let x = v.x * scale + start.x;
+    let y = v.y * scale + start.y;
     nalgebra_glm::Vec3::new(x, y, 0.0)
 }
 
 fn render2d(framebuffer: &mut Framebuffer, data: &Model) {
     let (maze_cell_width, maze_cell_height) = data.board.cell_dimensions;
-
-    data.board
-        .cells
-        .iter()
-        .enumerate()
-        .flat_map(|(j, row)| {
-            row.iter()
-                .enumerate()
-                .map(move |(i, cell)| (i as f32, j as f32, cell))
-        })
-        .for_each(|(i, j, char)| {
-            let mut current_x = i * maze_cell_width;
-            let start_y = j * maze_cell_height;
-
-            let end_x = current_x + maze_cell_width;
-            let end_y = start_y + maze_cell_height;
-
-            let color = from_cell_to_color(char);
-            framebuffer.set_current_color(color);
-
-            while current_x < end_x {
-                let mut current_y = start_y;
-                while current_y < end_y {
-                    let point = scale_to_fit(
-                        framebuffer,
-                        nalgebra_glm::Vec3::new(current_x, current_y, 0.0),
-                    );
-                    let _ = framebuffer.paint_point(point);
-                    current_y += 0.5;
-                }
-                current_x += 0.5;
-            }
-        });
+    let (start, scale) = minimap_layout(framebuffer, &data.board);
+
+    // Every pixel of the minimap is painted with the color of the cell below it.
+    let rows = data.board.cells.len() as f32 * maze_cell_height * scale;
+    let columns =
+        data.board.cells.first().map_or(0, |row| row.len()) as f32 * maze_cell_width * scale;
+    for y in 0..rows as usize {
+        for x in 0..columns as usize {
+            let i = ((x as f32 + 0.5) / scale / maze_cell_width) as usize;
+            let j = ((y as f32 + 0.5) / scale / maze_cell_height) as usize;
+            let cell = match data.board.cells.get(j).and_then(|row| row.get(i)) {
+                Some(cell) => cell,
+                None => continue,
+            };
+
+            framebuffer.set_current_color(from_cell_to_color(cell));
+            let _ = framebuffer.paint_point(nalgebra_glm::Vec3::new(
+                start.x + x as f32,
+                start.y + y as f32,
+                0.0,
+            ));
+        }
+    }
 
     let num_rays = 20;
     for i in 0..num_rays {
@@ -119,17 +245,26 @@ fn render2d(framebuffer: &mut Framebuffer, data: &Model) {
         cast_ray_2d(framebuffer, &data.board, &data.player, a);
     }
 
-    framebuffer.set_current_color(0xff0000);
     let half_height = 5;
     let half_width = 5;
-    for bunny in &data.lolibunnies {
-        let start_x = (bunny.position.x - half_width as f32) as usize;
-        let start_y = (bunny.position.y - half_height as f32) as usize;
+    let bunnies = data
+        .lolibunnies
+        .iter()
+        .map(|bunny| (0xff0000, bunny.position));
+    let hunters = data
+        .hunters
+        .iter()
+        .map(|hunter| (0xb040ff, hunter.position));
+    for (color, position) in bunnies.chain(hunters) {
+        framebuffer.set_current_color(color);
+        let start_x = (position.x - half_width as f32) as usize;
+        let start_y = (position.y - half_height as f32) as usize;
 
         for x in start_x..(start_x + half_width * 2) {
             for y in start_y..(start_y + half_height * 2) {
                 let point = scale_to_fit(
                     framebuffer,
+                    &data.board,
                     nalgebra_glm::Vec3::new(x as f32, y as f32, 0.0),
                 );
                 let _ = framebuffer.paint_point(point);
@@ -138,32 +273,40 @@ fn render2d(framebuffer: &mut Framebuffer, data: &Model) {
     }
 
     framebuffer.set_current_color(0x0000ff);
-    let point = scale_to_fit(framebuffer, vec2_to_vec3(&data.player.position));
+    let point = scale_to_fit(
+        framebuffer,
+        &data.board,
+        vec2_to_vec3(&data.player.position),
+    );
     let _ = framebuffer.paint_point(point);
 }
 
+/// How the splash, win and lose screens play, a frame every 4 ticks is around 15 per second.
+const SCREEN_ANIMATION: Animation = Animation {
+    ticks_per_frame: 4,
+    offset: 0,
+};
+
+/// Stretches the current frame of a full screen animation over the whole framebuffer.
+fn render_animated_screen(framebuffer: &mut Framebuffer, data: &Model, texture: &AnimatedTexture) {
+    let (framebuffer_width, framebuffer_height) = data.framebuffer_dimensions;
+    let t_frame = SCREEN_ANIMATION.frame(&data.clock, texture.frame_count);
+    for x in 0..framebuffer_width {
+        for y in 0..framebuffer_height {
+            let tx = x * texture.width as usize / framebuffer_width;
+            let ty = y * texture.height as usize / framebuffer_height;
+
+            let color = texture.get_pixel_color(t_frame, tx as u32, ty as u32);
+            framebuffer.set_current_color(color);
+            let _ = framebuffer.paint_point(nalgebra_glm::Vec3::new(x as f32, y as f32, 0.0));
+        }
+    }
+}
+
 fn render3d(framebuffer: &mut Framebuffer, data: &Model) {
     match data.status {
         GameStatus::SplashScreen => {
-            let (framebuffer_width, framebuffer_height) = data.framebuffer_dimensions;
-            let texture = &data.textures.splash_screen;
-            let t_frame = (SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .expect("Time went backwards!")
-                .as_millis()
-                / 60)
-                % texture.frame_count as u128;
-            for x in 0..framebuffer_width {
-                for y in 0..framebuffer_height {
-                    let tx = x * texture.width as usize / framebuffer_width;
-                    let ty = y * texture.height as usize / framebuffer_height;
-
-                    let color = texture.get_pixel_color(t_frame as usize, tx as u32, ty as u32);
-                    framebuffer.set_current_color(color);
-                    let _ =
-                        framebuffer.paint_point(nalgebra_glm::Vec3::new(x as f32, y as f32, 0.0));
-                }
-            }
+            render_animated_screen(framebuffer, data, &data.textures.splash_screen);
         }
         GameStatus::MainMenu => {
             let (framebuffer_width, framebuffer_height) = data.framebuffer_dimensions;
@@ -181,113 +324,351 @@ fn render3d(framebuffer: &mut Framebuffer, data: &Model) {
             }
         }
         GameStatus::YouLost => {
-            let (framebuffer_width, framebuffer_height) = data.framebuffer_dimensions;
-            let texture = &data.textures.loose_screen;
-            let t_frame = (SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .expect("Time went backwards!")
-                .as_millis()
-                / 60)
-                % texture.frame_count as u128;
-            for x in 0..framebuffer_width {
-                for y in 0..framebuffer_height {
-                    let tx = x * texture.width as usize / framebuffer_width;
-                    let ty = y * texture.height as usize / framebuffer_height;
-
-                    let color = texture.get_pixel_color(t_frame as usize, tx as u32, ty as u32);
-                    framebuffer.set_current_color(color);
-                    let _ =
-                        framebuffer.paint_point(nalgebra_glm::Vec3::new(x as f32, y as f32, 0.0));
-                }
-            }
+            render_animated_screen(framebuffer, data, &data.textures.loose_screen);
         }
         GameStatus::YouWon => {
-            let (framebuffer_width, framebuffer_height) = data.framebuffer_dimensions;
-            let texture = &data.textures.win_screen;
-            let t_frame = (SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .expect("Time went backwards!")
-                .as_millis()
-                / 60)
-                % texture.frame_count as u128;
-            for x in 0..framebuffer_width {
-                for y in 0..framebuffer_height {
-                    let tx = x * texture.width as usize / framebuffer_width;
-                    let ty = y * texture.height as usize / framebuffer_height;
-
-                    let color = texture.get_pixel_color(t_frame as usize, tx as u32, ty as u32);
-                    framebuffer.set_current_color(color);
-                    let _ =
-                        framebuffer.paint_point(nalgebra_glm::Vec3::new(x as f32, y as f32, 0.0));
-                }
-            }
+            render_animated_screen(framebuffer, data, &data.textures.win_screen);
+            let final_time = data.final_time.unwrap_or(data.clock);
+
+            // The totals of the whole campaign.
+            let lines = [
+                format!("score {}", data.score),
+                format!("time {}", format_time(final_time.seconds())),
+            ];
+            render_lines(framebuffer, &lines, framebuffer.height * 3 / 4);
         }
-        GameStatus::Gaming => {
-            let (framebuffer_width, framebuffer_height) = data.framebuffer_dimensions;
-            render_moon(framebuffer, data);
+        GameStatus::LevelComplete => {
+            let lines = [
+                format!("night {} survived", data.level + 1),
+                format!("score {}", data.score),
+                format!("time {}", format_time(data.clock.seconds())),
+                "press space".to_string(),
+            ];
+            render_lines(framebuffer, &lines, framebuffer.height / 4);
+        }
+        GameStatus::Gaming | GameStatus::Paused => {
+            let projection = Projection::new(data);
 
-            let _half_width = framebuffer_width as f32 / 2.0;
-            let half_height = framebuffer_height as f32 / 2.0;
-            let player = &data.player;
+            render_sky(framebuffer, data, &projection);
+            render_moon(framebuffer, data, &projection);
 
-            let mut z_buffer = vec![f32::INFINITY; framebuffer_width];
+            // The depth of every pixel, so sprites can be hidden behind walls
+            // while still being seen through the gaps of see-through walls.
+            let mut z_buffer = vec![f32::INFINITY; framebuffer.width * framebuffer.height];
 
             // Render 3D Screen...
-            let num_rays = framebuffer_width;
-            (0..num_rays).for_each(|i| {
-                let current_ray = i as f32 / num_rays as f32;
-                let orientation =
-                    player.orientation - (player.fov / 2.0) + (player.fov * current_ray);
+            let scene = Scene {
+                board: &data.board,
+                player: &data.player,
+                textures: &data.textures,
+                lighting: &data.lighting,
+                projection: &projection,
+                clock: &data.clock,
+            };
+            let workers = std::thread::available_parallelism().map_or(1, |n| n.get());
+            render_columns(framebuffer, &mut z_buffer, &scene, workers);
 
-                let intersect = cast_ray_3d(framebuffer, data, orientation);
+            // Render enemies
+            render_sprites(framebuffer, data, &z_buffer);
 
-                if intersect.distance < z_buffer[i] {
-                    z_buffer[i] = intersect.distance;
-                }
+            // Render HUD
+            render_minimap(framebuffer, data);
+        }
+    }
+}
 
-                let distance_to_wall = intersect.distance;
-                let distance_to_projection_plane = 6.0 * std::f32::consts::PI;
-
-                let stake_height = (half_height / distance_to_wall) * distance_to_projection_plane;
-
-                let stake_top = (half_height - (stake_height / 2.0)) as usize;
-                let stake_bottom = (half_height + (stake_height / 2.0)) as usize;
-
-                for y in stake_top..stake_bottom {
-                    let distance_from_center = ((framebuffer.width as f32 / 2.0 - i as f32)
-                        .powi(2)
-                        + (framebuffer.height as f32 / 2.0 - y as f32).powi(2))
-                    .sqrt();
-                    let color = match from_char_to_texture(&intersect.impact, &data.textures) {
-                        Some(texture) => {
-                            // Calculate tx and ty.
-                            // Return color from texture.
-                            let ty = (y as f32 - stake_top as f32) / stake_height
-                                * (texture.height as f32);
-                            let tx = intersect.bx * texture.width as f32;
-                            texture.get_pixel_color(tx as u32, ty as u32)
-                        }
-                        None => from_cell_to_color(&intersect.impact),
+/// Writes every line centered on the screen, one below the other, starting at `top`.
+fn render_lines(framebuffer: &mut Framebuffer, lines: &[String], top: usize) {
+    let scale = (framebuffer.height / 72).max(1);
+    let line_height = (GLYPH_HEIGHT + 3) * scale;
+    for (i, line) in lines.iter().enumerate() {
+        let x = framebuffer.width.saturating_sub(text_width(line, scale)) / 2;
+        let y = top + i * line_height;
+        draw_text(framebuffer, line, (x, y), scale, Color::white());
+    }
+}
+
+/// The parts of the model needed to render the columns of the 3D view.
+///
+/// Unlike `Model` it can be shared between threads.
+struct Scene<'a> {
+    board: &'a Board,
+    player: &'a Player,
+    textures: &'a GameTextures,
+    lighting: &'a LightingSettings,
+    projection: &'a Projection,
+    clock: &'a AnimationClock,
+}
+
+/// A band of rows of the framebuffer and the z-buffer that's painted by a single thread.
+struct Band<'a> {
+    /// The width of the framebuffer.
+    width: usize,
+    /// The height of the framebuffer.
+    height: usize,
+    /// The row of the framebuffer where the band starts.
+    first_row: usize,
+    pixels: &'a mut [u32],
+    depths: &'a mut [f32],
+}
+
+impl Band<'_> {
+    /// The rows of the framebuffer inside the band.
+    fn rows(&self) -> Range<usize> {
+        self.first_row..self.first_row + self.pixels.len() / self.width
+    }
+
+    fn paint(&mut self, x: usize, y: usize, color: Color) {
+        self.pixels[(y - self.first_row) * self.width + x] = color.into();
+    }
+
+    fn depth(&mut self, x: usize, y: usize) -> &mut f32 {
+        &mut self.depths[(y - self.first_row) * self.width + x]
+    }
+}
+
+/// Renders the walls, floors and ceilings of every column using `workers` threads.
+///
+/// First the rays of every column are cast, splitting the columns between the threads.
+/// Since the framebuffer is stored row by row, it's then split into bands of rows so
+/// every thread paints its own slice of the buffer and the z-buffer.
+/// Every pixel only depends on its column, so the result is the same for any number of threads.
+fn render_columns(
+    framebuffer: &mut Framebuffer,
+    z_buffer: &mut [f32],
+    scene: &Scene,
+    workers: usize,
+) {
+    let (width, height) = (framebuffer.width, framebuffer.height);
+    if width == 0 || height == 0 {
+        return;
+    }
+    let workers = workers.max(1);
+
+    let columns_per_worker = width.div_ceil(workers);
+    let layers: Vec<Vec<Intersect>> = thread::scope(|s| {
+        let workers: Vec<_> = (0..width)
+            .step_by(columns_per_worker)
+            .map(|start| {
+                let columns = start..(start + columns_per_worker).min(width);
+                s.spawn(move || {
+                    columns
+                        .map(|x| {
+                            let orientation =
+                                scene.player.orientation + scene.projection.column_angle(x);
+                            cast_ray_layers(scene.board, scene.player, orientation)
+                        })
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().expect("A render thread panicked!"))
+            .collect()
+    });
+
+    let band_size = height.div_ceil(workers) * width;
+    thread::scope(|s| {
+        let layers = &layers;
+        framebuffer
+            .buffer
+            .chunks_mut(band_size)
+            .zip(z_buffer.chunks_mut(band_size))
+            .enumerate()
+            .for_each(|(n, (pixels, depths))| {
+                s.spawn(move || {
+                    let mut band = Band {
+                        width,
+                        height,
+                        first_row: n * band_size / width,
+                        pixels,
+                        depths,
                     };
+                    layers
+                        .iter()
+                        .enumerate()
+                        .for_each(|(x, layers)| render_column(&mut band, scene, x, layers));
+                });
+            });
+    });
+}
 
-                    framebuffer.set_current_color(apply_lantern_effect(
-                        &color,
-                        distance_from_center,
-                        framebuffer_width as f32,
-                    ));
+/// Paints the rows of the column `x` that are inside the band,
+/// given the walls its ray went through.
+fn render_column(band: &mut Band, scene: &Scene, x: usize, layers: &[Intersect]) {
+    let ray_angle = scene.projection.column_angle(x);
+    let opaque_wall = layers
+        .last()
+        .filter(|intersect| !intersect.impact.is_see_through());
+
+    let (wall_top, wall_bottom) = match opaque_wall {
+        Some(intersect) => {
+            // Nothing behind an opaque wall can be seen.
+            let distance_to_wall = intersect.distance * ray_angle.cos();
+            band.rows()
+                .for_each(|y| *band.depth(x, y) = distance_to_wall);
+
+            render_wall(band, scene, x, ray_angle, intersect)
+        }
+        None => {
+            let horizon = scene.projection.horizon.max(0.0) as usize;
+            (horizon, horizon)
+        }
+    };
 
-                    // framebuffer.set_current_color(color);
-                    let _ =
-                        framebuffer.paint_point(nalgebra_glm::Vec3::new(i as f32, y as f32, 0.0));
+    render_surfaces(band, scene, x, ray_angle, wall_top, wall_bottom);
+
+    // See-through walls are composited from back to front over whatever is behind them.
+    layers
+        .iter()
+        .rev()
+        .filter(|intersect| intersect.impact.is_see_through())
+        .for_each(|intersect| {
+            render_wall(band, scene, x, ray_angle, intersect);
+        });
+}
+
+/// Paints the stake of the wall hit by the ray of the column `x`,
+/// skipping the transparent pixels of its texture.
+///
+/// Returns the rows where the stake starts and ends.
+fn render_wall(
+    band: &mut Band,
+    scene: &Scene,
+    x: usize,
+    ray_angle: f32,
+    intersect: &Intersect,
+) -> (usize, usize) {
+    let projection = scene.projection;
+    // Using the euclidean distance makes walls bow outwards.
+    let distance_to_wall = intersect.distance * ray_angle.cos();
+
+    let stake_height = projection.project_height(distance_to_wall);
+    let (stake_top, stake_bottom) = projection.wall_rows(distance_to_wall);
+
+    // Walls are lit by the cell in front of the face the ray hit.
+    let orientation = scene.player.orientation + ray_angle;
+    let direction = Vec2::new(orientation.cos(), orientation.sin());
+    let baked_light = scene.board.light_at(&(intersect.point - direction * 0.01));
+
+    // Every wall cell has its own phase, so torches don't flicker in sync.
+    let (block_width, block_height) = scene.board.cell_dimensions;
+    let wall_point = intersect.point + direction * 0.01;
+    let phase = (wall_point.x / block_width) as u64 * 3 + (wall_point.y / block_height) as u64 * 5;
+    let animation = Animation::new(TORCH_FLICKER_TICKS, phase * TORCH_FLICKER_TICKS);
+    let texture = from_char_to_texture(&intersect.impact, scene.textures, scene.clock, &animation);
+
+    let start_y = stake_top.max(0.0) as usize;
+    let end_y = (stake_bottom.max(0.0) as usize).min(band.height);
+    let rows = band.rows();
+    for y in start_y.max(rows.start)..end_y.min(rows.end) {
+        let distance_from_center = ((band.width as f32 / 2.0 - x as f32).powi(2)
+            + (band.height as f32 / 2.0 - y as f32).powi(2))
+        .sqrt();
+        let color = match texture {
+            Some(texture) => {
+                // Calculate tx and ty.
+                // Return color from texture.
+                let ty = (y as f32 - stake_top) / stake_height * (texture.height as f32);
+                let tx = intersect.bx * texture.width as f32;
+                if texture.is_transparent(tx as u32, ty as u32) {
+                    continue;
                 }
-            });
+                texture.get_pixel_color(tx as u32, ty as u32)
+            }
+            None => from_cell_to_color(&intersect.impact),
+        };
+
+        let color = color * scene.lighting.side_brightness(intersect.side);
+        band.paint(
+            x,
+            y,
+            scene.lighting.shade(
+                &color,
+                intersect.distance,
+                distance_from_center,
+                band.width as f32,
+                baked_light,
+            ),
+        );
+
+        let depth = band.depth(x, y);
+        *depth = depth.min(distance_to_wall);
+    }
 
-            // Render enemies
-            render_lolibunny(framebuffer, data, &z_buffer);
+    (start_y, end_y)
+}
 
-            // Render HUD
-            render_minimap(framebuffer, data);
+/// Casts the floor below `wall_bottom` and the ceiling above `wall_top` for the column `x`.
+///
+/// Cells without a ceiling are left untouched so the sky can be seen through them.
+fn render_surfaces(
+    band: &mut Band,
+    scene: &Scene,
+    x: usize,
+    ray_angle: f32,
+    wall_top: usize,
+    wall_bottom: usize,
+) {
+    let Scene {
+        board,
+        player,
+        textures,
+        projection,
+        ..
+    } = scene;
+    let (block_width, block_height) = board.cell_dimensions;
+    let orientation = player.orientation + ray_angle;
+    let direction = Vec2::new(orientation.cos(), orientation.sin());
+
+    let rows = band.rows();
+    let rows = (rows.start..wall_top.min(rows.end)).chain(wall_bottom.max(rows.start)..rows.end);
+    for y in rows {
+        let distance = projection.surface_distance(y) / ray_angle.cos();
+        let point = player.position + direction * distance;
+
+        let i = (point.x / block_width).floor();
+        let j = (point.y / block_height).floor();
+        if i < 0.0 || j < 0.0 {
+            continue;
         }
+
+        let (i, j) = (i as usize, j as usize);
+        let surface = if y < wall_top {
+            board
+                .ceilings
+                .get(j)
+                .and_then(|row| row.get(i))
+                .copied()
+                .flatten()
+        } else {
+            board.floors.get(j).and_then(|row| row.get(i)).copied()
+        };
+        let texture = match surface {
+            Some(surface) => from_surface_to_texture(&surface, textures),
+            None => continue,
+        };
+
+        let tx = (point.x / block_width).fract() * texture.width as f32;
+        let ty = (point.y / block_height).fract() * texture.height as f32;
+        let color = texture.get_pixel_color(tx as u32, ty as u32);
+
+        let distance_from_center = ((band.width as f32 / 2.0 - x as f32).powi(2)
+            + (band.height as f32 / 2.0 - y as f32).powi(2))
+        .sqrt();
+        band.paint(
+            x,
+            y,
+            scene.lighting.shade(
+                &color,
+                distance,
+                distance_from_center,
+                band.width as f32,
+                board.light_at(&point),
+            ),
+        );
     }
 }
 
@@ -295,22 +676,53 @@ fn render_minimap(framebuffer: &mut Framebuffer, data: &Model) {
     render2d(framebuffer, data);
 }
 
-fn apply_lantern_effect(color: &Color, distance_from_center: f32, framebuffer_width: f32) -> Color {
-    *color
-    // color.change_brightness_by((framebuffer_width / distance_from_center - 5.0).clamp(0.2, 1.0))
+/// Paints the panorama of the night sky above the horizon.
+/// The panorama wraps around the player, so it scrolls as they turn.
+fn render_sky(framebuffer: &mut Framebuffer, data: &Model, projection: &Projection) {
+    let texture = &data.textures.night_sky;
+    let horizon = projection.horizon.max(0.0) as usize;
+
+    for x in 0..framebuffer.width {
+        let azimuth = data.player.orientation + projection.column_angle(x);
+        let u = (azimuth / std::f32::consts::TAU).rem_euclid(1.0);
+        let tx = ((u * texture.width as f32) as u32).min(texture.width - 1);
+
+        for y in 0..horizon.min(framebuffer.height) {
+            let elevation =
+                ((projection.horizon - (y as f32 + 0.5)) / projection.plane_distance).atan();
+            let v = 1.0 - (elevation / SKY_MAX_ELEVATION).min(1.0);
+            let ty = ((v * texture.height as f32) as u32).min(texture.height - 1);
+
+            framebuffer.set_current_color(texture.get_pixel_color(tx, ty));
+            let _ = framebuffer.paint_point(nalgebra_glm::Vec3::new(x as f32, y as f32, 0.0));
+        }
+    }
 }
 
-fn render_moon(framebuffer: &mut Framebuffer, data: &Model) {
+/// Paints the moon in its place in the sky.
+///
+/// The moon travels through the sky as `moon_phase` advances,
+/// so it can only be seen when the player looks towards it.
+fn render_moon(framebuffer: &mut Framebuffer, data: &Model, projection: &Projection) {
     let Model {
         textures,
         moon_phase,
+        player,
         ..
     } = data;
 
-    let radius = 100.0;
-    let center_x = moon_phase * framebuffer.width as f32;
+    let azimuth = MOON_RISE_AZIMUTH + moon_phase * MOON_SWEEP;
+    let relative_angle = normalize_angle(azimuth - player.orientation);
+    if relative_angle.cos() <= 0.0 {
+        return;
+    }
+
+    let elevation = MOON_MIN_ELEVATION
+        + (MOON_MAX_ELEVATION - MOON_MIN_ELEVATION) * (moon_phase * std::f32::consts::PI).sin();
 
-    let center_y = 0.0005 * (center_x - framebuffer.width as f32 / 2.0).powi(2) + 100.0;
+    let radius = projection.plane_distance * MOON_ANGULAR_RADIUS.tan();
+    let center_x = projection.screen_x(relative_angle);
+    let center_y = projection.horizon - elevation.tan() * projection.plane_distance;
 
     let start_x = (center_x - radius) as isize;
     let start_y = (center_y - radius) as isize;
@@ -324,10 +736,12 @@ fn render_moon(framebuffer: &mut Framebuffer, data: &Model) {
             let distance_to_center =
                 ((x as f32 - center_x).powi(2) + (y as f32 - center_y).powi(2)).sqrt();
             if distance_to_center <= radius {
-                // let t_frame_idx = center_x as usize % textures.moon.frame_count;
                 let tx = ((x - start_x) as f32 * texture.width as f32) / (radius * 2.0);
                 let ty = ((y - start_y) as f32 * texture.height as f32) / (radius * 2.0);
-                let color = texture.get_pixel_color(tx as u32, ty as u32);
+                let color = texture.get_pixel_color(
+                    (tx as u32).min(texture.width - 1),
+                    (ty as u32).min(texture.height - 1),
+                );
                 framebuffer.set_current_color(color);
                 let _ = framebuffer.paint_point(nalgebra_glm::Vec3::new(x as f32, y as f32, 0.0));
             }
@@ -335,60 +749,157 @@ fn render_moon(framebuffer: &mut Framebuffer, data: &Model) {
     }
 }
 
-fn render_lolibunny(framebuffer: &mut Framebuffer, data: &Model, z_buffer: &[f32]) {
+/// Wraps the given angle into the range `[-PI, PI]`.
+fn normalize_angle(angle: f32) -> f32 {
+    let tau = std::f32::consts::TAU;
+    angle - tau * ((angle + std::f32::consts::PI) / tau).floor()
+}
+
+/// Draws the sprites of everything in the maze, from the farthest to the nearest,
+/// hiding the parts that are behind walls.
+fn render_sprites(framebuffer: &mut Framebuffer, data: &Model, z_buffer: &[f32]) {
     let Model {
         player,
         lolibunnies,
+        hunters,
         textures,
         ..
     } = data;
-    lolibunnies.iter().for_each(|enemy| {
+    let projection = Projection::new(data);
+
+    let mut sprites: Vec<Sprite> = lolibunnies
+        .iter()
+        .map(HasSprite::sprite)
+        .chain(hunters.iter().map(HasSprite::sprite))
+        .collect();
+    sort_far_to_near(&mut sprites, &player.position);
+
+    sprites.iter().for_each(|sprite| {
         let sprite_a =
-            (enemy.position.y - player.position.y).atan2(enemy.position.x - player.position.x);
+            (sprite.position.y - player.position.y).atan2(sprite.position.x - player.position.x);
+        let relative_angle = normalize_angle(sprite_a - player.orientation);
 
-        let sprite_distance = ((player.position.x - enemy.position.x).powi(2)
-            + (player.position.y - enemy.position.y).powi(2))
-        .sqrt();
+        // Sprites behind the player can't be projected.
+        if relative_angle.cos() <= 0.0 {
+            return;
+        }
 
-        let (framebuffer_width, framebuffer_height) = data.framebuffer_dimensions;
-        let framebuffer_height = framebuffer_height as f32;
-        let framebuffer_width = framebuffer_width as f32;
+        let euclidean_distance = nalgebra_glm::distance(&player.position, &sprite.position);
+        let sprite_distance = euclidean_distance * relative_angle.cos();
+        let baked_light = data.board.light_at(&sprite.position);
 
-        let sprite_width = textures.lolibunny.width as f32;
-        let sprite_height = textures.lolibunny.height as f32;
+        let texture = from_sprite_texture(sprite, &player.position, textures, &data.clock);
+        let sprite_width = texture.width as f32;
+        let sprite_height = texture.height as f32;
 
         let sprite_ratio = sprite_width / sprite_height; // width / height
-        let rendered_sprite_height = (framebuffer_height / sprite_distance) * 9.0;
+        let rendered_sprite_height = projection.project_height(sprite_distance) * sprite.scale;
         let rendered_sprite_width = rendered_sprite_height * sprite_ratio;
-        let start_y = ((framebuffer_height / 2.0) - (rendered_sprite_height / 2.0)) as isize;
-        let start_x = ((sprite_a - player.orientation) * (framebuffer_height / player.fov)
-            + (framebuffer_width / 2.0)
-            - (rendered_sprite_height / 2.0)) as isize;
 
-        let end_x = (start_x as f32 + rendered_sprite_width) as isize;
-        let end_y = (start_y as f32 + rendered_sprite_height) as isize;
+        // Sprites stand on the floor.
+        let (_, floor_y) = projection.wall_rows(sprite_distance);
+        let start_y = floor_y - rendered_sprite_height;
+        let start_x = projection.screen_x(relative_angle) - (rendered_sprite_width / 2.0);
+
+        // Only the part of the sprite inside the screen is drawn.
+        let first_x = start_x.max(0.0) as usize;
+        let first_y = start_y.max(0.0) as usize;
+        let end_x = ((start_x + rendered_sprite_width).max(0.0) as usize).min(framebuffer.width);
+        let end_y = (floor_y.max(0.0) as usize).min(framebuffer.height);
+
+        for x in first_x..end_x {
+            for y in first_y..end_y {
+                if sprite_distance >= z_buffer[y * framebuffer.width + x] {
+                    continue;
+                }
 
-        for x in start_x..(end_x) {
-            if sprite_distance >= z_buffer[x.clamp(0, framebuffer_width as isize - 1) as usize] {
-                continue;
-            }
-            for y in start_y..(end_y) {
-                let tx = (x as f32 - start_x as f32) * sprite_width / rendered_sprite_width;
-                let ty = (y as f32 - start_y as f32) * sprite_height / rendered_sprite_height;
+                let tx = ((x as f32 - start_x) * sprite_width / rendered_sprite_width) as u32;
+                let ty = ((y as f32 - start_y) * sprite_height / rendered_sprite_height) as u32;
+                let (tx, ty) = (tx.min(texture.width - 1), ty.min(texture.height - 1));
+                if texture.is_transparent(tx, ty) {
+                    continue;
+                }
 
-                let color = textures.lolibunny.get_pixel_color(tx as u32, ty as u32);
-                let distance_from_center = ((x as f32 - framebuffer_width / 2.0).powi(2)
-                    + (y as f32 - framebuffer_height / 2.0).powi(2))
+                let color = texture.get_pixel_color(tx, ty);
+                let distance_from_center = ((x as f32 - projection.half_width).powi(2)
+                    + (y as f32 - projection.half_height).powi(2))
                 .sqrt();
-                // framebuffer.set_current_color(color);
 
-                framebuffer.set_current_color(apply_lantern_effect(
+                framebuffer.set_current_color(data.lighting.shade(
                     &color,
+                    euclidean_distance,
                     distance_from_center,
-                    framebuffer_width,
+                    framebuffer.width as f32,
+                    baked_light,
                 ));
                 let _ = framebuffer.paint_point(nalgebra_glm::Vec3::new(x as f32, y as f32, 0.0));
             }
         }
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_threaded_columns_match_a_single_thread() {
+        let cells = ["+-+-+-+", "|  f  |", "+ + +-+", "|     |", "+-+-+-+"]
+            .iter()
+            .map(|row| {
+                row.chars()
+                    .map(|c| match c {
+                        '+' => BoardCell::PillarWall,
+                        '|' => BoardCell::VerticalWall,
+                        '-' => BoardCell::HorizontalWall,
+                        'f' => BoardCell::Fence,
+                        _ => BoardCell::Empty,
+                    })
+                    .collect()
+            })
+            .collect();
+        let mut board = Board::new(cells, (10.0, 10.0));
+        board.ceilings[3] = vec![Some(Surface::Planks); 7];
+
+        let player = Player {
+            position: Vec2::new(15.0, 35.0),
+            orientation: -0.6,
+            fov: std::f32::consts::FRAC_PI_2,
+            pitch: 0.0,
+            eye_height: 5.0,
+            vertical_speed: None,
+            crouching: false,
+        };
+        let textures = GameTextures::new("night_assets/");
+        let lighting = LightingSettings::default();
+        let (width, height) = (97, 61);
+        let projection = Projection {
+            plane_distance: width as f32 / 2.0,
+            half_width: width as f32 / 2.0,
+            half_height: height as f32 / 2.0,
+            horizon: height as f32 / 2.0 + 4.0,
+            wall_height: 10.0,
+            eye_height: 5.0,
+        };
+        let scene = Scene {
+            board: &board,
+            player: &player,
+            textures: &textures,
+            lighting: &lighting,
+            projection: &projection,
+            clock: &AnimationClock::default(),
+        };
+
+        let render_with = |workers| {
+            let mut framebuffer = Framebuffer::new(width, height);
+            let mut z_buffer = vec![f32::INFINITY; width * height];
+            render_columns(&mut framebuffer, &mut z_buffer, &scene, workers);
+            (framebuffer.buffer, z_buffer)
+        };
+
+        let single_thread = render_with(1);
+        for workers in [2, 3, 8] {
+            assert!(render_with(workers) == single_thread);
+        }
+    }
+}
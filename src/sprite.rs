@@ -0,0 +1,146 @@
+use glm::Vec2;
+
+use crate::{
+    animation::Animation,
+    enemies::{Hunter, LoliBunny},
+};
+
+/// The color painted on the parts of a sprite texture that should be see-through.
+pub const SPRITE_COLOR_KEY: u32 = 0xff00ff;
+
+/// The textures a sprite can be drawn with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpriteTexture {
+    LoliBunny,
+    Hunter,
+}
+
+/// A billboard, something drawn as a flat image that always faces the player.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sprite {
+    pub position: Vec2,
+    pub texture: SpriteTexture,
+    /// The angle the entity is looking at,
+    /// directional textures use it to show the entity from the right side.
+    pub orientation: f32,
+    /// Which frame of an animated texture is shown.
+    pub animation: Animation,
+    /// The height of the sprite compared to the height of a wall.
+    /// Sprites stand on the floor, so smaller ones don't float.
+    pub scale: f32,
+}
+
+/// Anything that's drawn as a sprite.
+pub trait HasSprite {
+    fn sprite(&self) -> Sprite;
+}
+
+impl HasSprite for LoliBunny {
+    fn sprite(&self) -> Sprite {
+        Sprite {
+            position: self.position,
+            texture: SpriteTexture::LoliBunny,
+            orientation: self.orientation,
+            animation: self.animation,
+            scale: 1.0,
+        }
+    }
+}
+
+impl HasSprite for Hunter {
+    fn sprite(&self) -> Sprite {
+        Sprite {
+            position: self.position,
+            texture: SpriteTexture::Hunter,
+            orientation: self.orientation,
+            animation: Animation::new(1, 0),
+            scale: 1.1,
+        }
+    }
+}
+
+/// The number of rotations in a directional sprite sheet.
+pub const SPRITE_ROTATIONS: usize = 8;
+
+/// Picks the rotation of a directional sprite that `viewer` sees.
+///
+/// Rotation `0` is the entity seen from the front,
+/// the next ones go around it in the direction angles grow,
+/// each one `45°` after the previous.
+pub fn rotation_index(sprite: &Sprite, viewer: &Vec2) -> usize {
+    let to_viewer = viewer - sprite.position;
+    let view_angle = to_viewer.y.atan2(to_viewer.x) - sprite.orientation;
+    let step = std::f32::consts::TAU / SPRITE_ROTATIONS as f32;
+
+    (view_angle / step)
+        .round()
+        .rem_euclid(SPRITE_ROTATIONS as f32) as usize
+}
+
+/// Sorts the sprites from the farthest to the nearest to `viewer`,
+/// so nearer sprites are painted over farther ones.
+pub fn sort_far_to_near(sprites: &mut [Sprite], viewer: &Vec2) {
+    sprites.sort_by(|a, b| {
+        let a = glm::distance2(&a.position, viewer);
+        let b = glm::distance2(&b.position, viewer);
+        b.total_cmp(&a)
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sprite_at(x: f32, y: f32) -> Sprite {
+        Sprite {
+            position: Vec2::new(x, y),
+            texture: SpriteTexture::LoliBunny,
+            orientation: 0.0,
+            animation: Animation::new(1, 0),
+            scale: 1.0,
+        }
+    }
+
+    #[test]
+    fn test_rotation_follows_the_viewer_around_the_sprite() {
+        let sprite = sprite_at(0.0, 0.0);
+
+        assert_eq!(rotation_index(&sprite, &Vec2::new(10.0, 0.0)), 0);
+        assert_eq!(rotation_index(&sprite, &Vec2::new(10.0, 10.0)), 1);
+        assert_eq!(rotation_index(&sprite, &Vec2::new(0.0, 10.0)), 2);
+        assert_eq!(rotation_index(&sprite, &Vec2::new(-10.0, 0.1)), 4);
+        assert_eq!(rotation_index(&sprite, &Vec2::new(-10.0, -0.1)), 4);
+        assert_eq!(rotation_index(&sprite, &Vec2::new(10.0, -10.0)), 7);
+        assert_eq!(rotation_index(&sprite, &Vec2::new(10.0, -1.0)), 0);
+    }
+
+    #[test]
+    fn test_turning_the_sprite_changes_the_rotation() {
+        let mut sprite = sprite_at(0.0, 0.0);
+        sprite.orientation = std::f32::consts::FRAC_PI_2;
+
+        assert_eq!(rotation_index(&sprite, &Vec2::new(0.0, 10.0)), 0);
+        assert_eq!(rotation_index(&sprite, &Vec2::new(10.0, 0.0)), 6);
+    }
+
+    #[test]
+    fn test_sprites_are_sorted_far_to_near() {
+        let mut sprites = vec![
+            sprite_at(1.0, 0.0),
+            sprite_at(-5.0, 0.0),
+            sprite_at(0.0, 3.0),
+        ];
+
+        sort_far_to_near(&mut sprites, &Vec2::zeros());
+
+        let order: Vec<_> = sprites.iter().map(|s| s.position).collect();
+        assert_eq!(
+            order,
+            vec![
+                Vec2::new(-5.0, 0.0),
+                Vec2::new(0.0, 3.0),
+                Vec2::new(1.0, 0.0)
+            ]
+        );
+    }
+}
@@ -0,0 +1,48 @@
+//! Small boards and players for the unit tests.
+
+use glm::Vec2;
+
+use crate::{
+    game::orient_doors, raycaster::WallSide, Board, BoardCell, Door, Exit, Player, PushWall,
+};
+
+/// A board from rows of maze characters, like the ones of the maze files:
+/// `+`, `|` and `-` are walls, `d` doors, `s` push walls, `f` fences, `b` bars and `g` the exit.
+/// Anything else is an empty cell, and every cell is 10 units wide.
+///
+/// Doors span between the walls around them, as they do when a maze is loaded.
+pub fn board(rows: &[&str]) -> Board {
+    let cells: Vec<Vec<BoardCell>> = rows
+        .iter()
+        .map(|row| {
+            row.chars()
+                .map(|c| match c {
+                    '+' => BoardCell::PillarWall,
+                    '|' => BoardCell::VerticalWall,
+                    '-' => BoardCell::HorizontalWall,
+                    'd' => BoardCell::Door(Door::new(WallSide::Horizontal)),
+                    's' => BoardCell::PushWall(PushWall::new()),
+                    'f' => BoardCell::Fence,
+                    'b' => BoardCell::Bars,
+                    'g' => BoardCell::Goal(Exit::new()),
+                    _ => BoardCell::Empty,
+                })
+                .collect()
+        })
+        .collect();
+
+    Board::new(orient_doors(&cells), (10.0, 10.0))
+}
+
+/// A standing player at `position` looking east.
+pub fn player_at(position: Vec2) -> Player {
+    Player {
+        position,
+        orientation: 0.0,
+        fov: std::f32::consts::FRAC_PI_2,
+        pitch: 0.0,
+        eye_height: 5.0,
+        vertical_speed: None,
+        crouching: false,
+    }
+}
@@ -1,18 +1,32 @@
 use std::{fs::File, io::BufReader};
 
 use image::{
-    codecs::gif::GifDecoder, AnimationDecoder, Frame, GenericImageView, ImageDecoder, ImageReader,
-    Pixel,
+    codecs::gif::GifDecoder, AnimationDecoder, Frame, GenericImageView, ImageReader, Pixel,
 };
 
-use crate::color::Color;
+use crate::{
+    bmp::BmpImage,
+    color::Color,
+    sprite::{SPRITE_COLOR_KEY, SPRITE_ROTATIONS},
+};
 
 pub struct GameTextures {
     pub horizontal_wall: Texture,
     pub vertical_wall: Texture,
     pub corner_wall: Texture,
-    pub lolibunny: Texture,
+    pub door: Texture,
+    pub fence: Texture,
+    pub bars: Texture,
+    pub torch: AnimatedTexture,
+    pub exit_locked: Texture,
+    pub exit_open: Texture,
+    pub lolibunny: DirectionalTexture,
+    pub hunter: Texture,
+    pub grass: Texture,
+    pub cobblestone: Texture,
+    pub planks: Texture,
     pub moon: Texture,
+    pub night_sky: Texture,
     pub start_screen: Texture,
     pub loose_screen: AnimatedTexture,
     pub win_screen: AnimatedTexture,
@@ -20,12 +34,28 @@ pub struct GameTextures {
 }
 
 impl GameTextures {
+    /// The frames of the flame of the torches.
+    pub const TORCH_FRAMES: u32 = 4;
+    /// The frames of the hop of the lolibunnies, for every rotation.
+    pub const LOLIBUNNY_FRAMES: u32 = 4;
+
     pub fn new(asset_dir: &str) -> Self {
         let horizontal_wall = format!("{}{}", asset_dir, "small_wall.jpg");
         let vertical_wall = format!("{}{}", asset_dir, "large_wall.jpg");
         let corner_wall = format!("{}{}", asset_dir, "corner.jpg");
-        let lolibunny = format!("{}{}", asset_dir, "lolibunny.jpg");
+        let door = format!("{}{}", asset_dir, "door.jpg");
+        let fence = format!("{}{}", asset_dir, "fence.png");
+        let bars = format!("{}{}", asset_dir, "bars.png");
+        let torch = format!("{}{}", asset_dir, "torch_sheet.png");
+        let exit_locked = format!("{}{}", asset_dir, "exit_locked.png");
+        let exit_open = format!("{}{}", asset_dir, "exit_open.png");
+        let lolibunny = format!("{}{}", asset_dir, "lolibunny_sheet.png");
+        let hunter = format!("{}{}", asset_dir, "hunter.png");
+        let grass = format!("{}{}", asset_dir, "grass.jpg");
+        let cobblestone = format!("{}{}", asset_dir, "cobblestone.jpg");
+        let planks = format!("{}{}", asset_dir, "planks.jpg");
         let moon = format!("{}{}", asset_dir, "moon.jpg");
+        let night_sky = format!("{}{}", asset_dir, "night_sky.jpg");
         let start_screen = format!("{}{}", asset_dir, "start_screen.jpg");
         let loose_screen = format!("{}{}", asset_dir, "loose_screen.gif");
         let win_screen = format!("{}{}", asset_dir, "win_screen.gif");
@@ -34,20 +64,46 @@ impl GameTextures {
         let horizontal_wall = Texture::new(&horizontal_wall);
         let vertical_wall = Texture::new(&vertical_wall);
         let corner_wall = Texture::new(&corner_wall);
-        let lolibunny = Texture::new(&lolibunny);
+        let door = Texture::new(&door);
+        let fence = Texture::new(&fence);
+        let bars = Texture::new(&bars);
+        let torch = AnimatedTexture::from_sheet(&torch, GameTextures::TORCH_FRAMES);
+        let exit_locked = Texture::new(&exit_locked);
+        let exit_open = Texture::new(&exit_open);
+        let lolibunny = DirectionalTexture::new(
+            &lolibunny,
+            GameTextures::LOLIBUNNY_FRAMES,
+            SPRITE_COLOR_KEY.into(),
+        );
+        let hunter = Texture::with_color_key(&hunter, SPRITE_COLOR_KEY.into());
+        let grass = Texture::new(&grass);
+        let cobblestone = Texture::new(&cobblestone);
+        let planks = Texture::new(&planks);
         let start_screen = Texture::new(&start_screen);
         let loose_screen = AnimatedTexture::new(&loose_screen);
         let win_screen = AnimatedTexture::new(&win_screen);
         let splash_screen = AnimatedTexture::new(&splash_screen);
 
         let moon = Texture::new(&moon);
+        let night_sky = Texture::new(&night_sky);
 
         GameTextures {
             horizontal_wall,
             vertical_wall,
             corner_wall,
+            door,
+            fence,
+            bars,
+            torch,
+            exit_locked,
+            exit_open,
             lolibunny,
+            hunter,
+            grass,
+            cobblestone,
+            planks,
             moon,
+            night_sky,
             start_screen,
             loose_screen,
             win_screen,
@@ -56,16 +112,51 @@ impl GameTextures {
     }
 }
 
+#[derive(Clone)]
 pub struct Texture {
     pub width: u32,
     pub height: u32,
     colors: Vec<Color>,
+    alphas: Vec<u8>,
+}
+
+/// A sprite seen from different angles, every one of them animated.
+pub struct DirectionalTexture {
+    rotations: Vec<AnimatedTexture>,
+}
+
+impl DirectionalTexture {
+    /// Loads a sprite sheet with the rotations side by side
+    /// and the frames of their animation one below the other.
+    /// Pixels of the `key` color are transparent.
+    pub fn new(file_path: &str, frame_count: u32, key: Color) -> Self {
+        let sheet = Texture::with_color_key(file_path, key);
+        let frames = sheet.split(SPRITE_ROTATIONS as u32, frame_count);
+        let rotations = (0..SPRITE_ROTATIONS)
+            .map(|i| {
+                let frames = frames
+                    .iter()
+                    .skip(i)
+                    .step_by(SPRITE_ROTATIONS)
+                    .map(Texture::clone)
+                    .collect();
+                AnimatedTexture::from_frames(frames)
+            })
+            .collect();
+
+        DirectionalTexture { rotations }
+    }
+
+    /// The animation of the given rotation, see `sprite::rotation_index`.
+    pub fn rotation(&self, index: usize) -> &AnimatedTexture {
+        &self.rotations[index % self.rotations.len()]
+    }
 }
 
 pub struct AnimatedTexture {
     pub width: u32,
     pub height: u32,
-    frames: Vec<Frame>,
+    frames: Vec<Texture>,
     pub frame_count: usize,
 }
 
@@ -73,27 +164,38 @@ impl AnimatedTexture {
     pub fn new(file_path: &str) -> Self {
         let file_in = BufReader::new(File::open(file_path).unwrap());
         let decoder = GifDecoder::new(file_in).unwrap();
-        let (width, height) = decoder.dimensions();
         let frames = decoder.into_frames();
         let frames = frames.collect_frames().expect("error decoding gif");
-        let frame_count = frames.len();
 
-        Self {
-            width,
-            height,
+        AnimatedTexture::from_frames(frames.iter().map(Texture::from_frame).collect())
+    }
+
+    /// Loads a sprite sheet with the frames side by side, all of them the same width.
+    pub fn from_sheet(file_path: &str, frame_count: u32) -> Self {
+        AnimatedTexture::from_frames(Texture::new(file_path).split(frame_count, 1))
+    }
+
+    fn from_frames(frames: Vec<Texture>) -> Self {
+        let first = frames
+            .first()
+            .expect("An animation needs at least one frame!");
+
+        AnimatedTexture {
+            width: first.width,
+            height: first.height,
+            frame_count: frames.len(),
             frames,
-            frame_count,
         }
     }
 
+    /// The texture of the frame `t`.
+    pub fn frame(&self, t: usize) -> &Texture {
+        &self.frames[t % self.frame_count]
+    }
+
     /// Get's the color of the pixel positioned on the frame `t`.
     pub fn get_pixel_color(&self, t: usize, x: u32, y: u32) -> Color {
-        let pixel = self.frames[t].buffer().get_pixel(x, y).to_rgb();
-        let r = pixel[0];
-        let g = pixel[1];
-        let b = pixel[2];
-
-        Color { r, g, b }
+        self.frame(t).get_pixel_color(x, y)
     }
 }
 
@@ -105,18 +207,20 @@ impl Texture {
 
         let size = width * height;
         let mut colors = vec![0xffffff.into(); size as usize];
+        let mut alphas = vec![u8::MAX; size as usize];
 
         // If I use flatmap and all that this get's reordered...
         // I don't know why
         for x in 0..width {
             for y in 0..height {
-                let pixel = image.get_pixel(x, y).to_rgb();
+                let pixel = image.get_pixel(x, y).to_rgba();
                 let r = pixel[0];
                 let g = pixel[1];
                 let b = pixel[2];
 
                 let idx = y * width + x;
                 colors[idx as usize] = Color { r, g, b };
+                alphas[idx as usize] = pixel[3];
             }
         }
 
@@ -124,6 +228,70 @@ impl Texture {
             width,
             height,
             colors,
+            alphas,
+        }
+    }
+
+    /// Loads a texture where every pixel of the `key` color is transparent.
+    pub fn with_color_key(file_path: &str, key: Color) -> Self {
+        let mut texture = Texture::new(file_path);
+        for (color, alpha) in texture.colors.iter().zip(texture.alphas.iter_mut()) {
+            if (color.r, color.g, color.b) == (key.r, key.g, key.b) {
+                *alpha = 0;
+            }
+        }
+
+        texture
+    }
+
+    /// Creates a texture from a frame of an animated image.
+    fn from_frame(frame: &Frame) -> Self {
+        let buffer = frame.buffer();
+        let (width, height) = buffer.dimensions();
+        let pixels = buffer.pixels();
+
+        Texture {
+            width,
+            height,
+            colors: pixels
+                .clone()
+                .map(|pixel| Color::new(pixel[0], pixel[1], pixel[2]))
+                .collect(),
+            alphas: pixels.map(|pixel| pixel[3]).collect(),
+        }
+    }
+
+    /// Cuts a sprite sheet into a grid of `columns` by `rows` textures,
+    /// returned row by row.
+    fn split(&self, columns: u32, rows: u32) -> Vec<Texture> {
+        let width = self.width / columns;
+        let height = self.height / rows;
+
+        (0..rows)
+            .flat_map(|row| (0..columns).map(move |column| (column * width, row * height)))
+            .map(|(x, y)| {
+                let pixels = (y..y + height).flat_map(|y| {
+                    let start = (y * self.width + x) as usize;
+                    start..start + width as usize
+                });
+
+                Texture {
+                    width,
+                    height,
+                    colors: pixels.clone().map(|idx| self.colors[idx]).collect(),
+                    alphas: pixels.map(|idx| self.alphas[idx]).collect(),
+                }
+            })
+            .collect()
+    }
+
+    /// Creates a texture from a decoded BMP, like a screenshot of the game.
+    pub fn from_bmp(image: BmpImage) -> Self {
+        Texture {
+            width: image.width as u32,
+            height: image.height as u32,
+            colors: image.buffer.into_iter().map(Color::from).collect(),
+            alphas: image.alphas,
         }
     }
 
@@ -131,4 +299,10 @@ impl Texture {
         let idx = y * self.width + x;
         self.colors[idx as usize]
     }
+
+    /// Whether the pixel should be skipped when painting, so whatever is behind it can be seen.
+    pub fn is_transparent(&self, x: u32, y: u32) -> bool {
+        let idx = y * self.width + x;
+        self.alphas[idx as usize] < u8::MAX / 2
+    }
 }